@@ -1,20 +1,22 @@
 use cosmwasm_std::{
-    entry_point, to_binary, Binary, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Reply, ReplyOn,
-    Response, StdResult, SubMsg, Uint128, WasmMsg,
+    entry_point, from_binary, to_binary, Binary, CosmosMsg, Deps, DepsMut, Env, MessageInfo,
+    Reply, ReplyOn, Response, StdError, StdResult, SubMsg, Uint128, WasmMsg,
 };
-use cw20::Cw20ExecuteMsg;
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
 use terraswap::asset::{Asset, AssetInfo};
 use terraswap::pair::ExecuteMsg as PairExecuteMsg;
 use terraswap::querier::{query_balance, query_token_balance};
 
 use white_whale::community_fund::msg::{ConfigResponse, ExecuteMsg, QueryMsg};
-use white_whale::denom::{UST_DENOM, WHALE_DENOM};
+use white_whale::denom::UST_DENOM;
 use white_whale::msg::AnchorMsg;
 use white_whale::query::anchor::query_aust_exchange_rate;
+use white_whale::query::terraswap::record_price as record_price_observation;
+use white_whale::wormhole::msg::{TokenBridgeExecuteMsg, TransferInfo};
 
 use crate::error::CommunityFundError;
-use crate::msg::InstantiateMsg;
-use crate::state::{State, ADMIN, STATE};
+use crate::msg::{Cw20HookMsg, InstantiateMsg};
+use crate::state::{State, ADMIN, BALANCE_OF, STATE, TOTAL_SUPPLY};
 
 /*
     The Community fund holds the protocol treasury and has control over the protocol owned liquidity.
@@ -23,6 +25,9 @@ use crate::state::{State, ADMIN, STATE};
 
 type CommunityFundResult = Result<Response, CommunityFundError>;
 
+// Reply id used to capture the sequence number emitted by the token bridge's InitiateTransfer
+const TOKEN_BRIDGE_TRANSFER_REPLY_ID: u64 = 1;
+
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
@@ -30,13 +35,16 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> StdResult<Response> {
     deps.api.addr_validate(&msg.whale_token_addr)?;
+    deps.api.addr_validate(&msg.token_bridge_addr)?;
 
     let state = State {
         whale_token_addr: deps.api.addr_canonicalize(&msg.whale_token_addr)?,
+        token_bridge_addr: deps.api.addr_canonicalize(&msg.token_bridge_addr)?,
     };
 
     STATE.save(deps.storage, &state)?;
     ADMIN.set(deps, Some(info.sender))?;
+    TOTAL_SUPPLY.save(deps.storage, &Uint128::zero())?;
 
     Ok(Response::default())
 }
@@ -47,7 +55,13 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> C
             spend_whale(deps.as_ref(), info, recipient, amount)
         }
         ExecuteMsg::Burn { amount } => burn_whale(deps.as_ref(), info, amount),
-        ExecuteMsg::Deposit {} => deposit(deps, &env, info),
+        ExecuteMsg::SpendCrossChain {
+            recipient_chain,
+            recipient_address,
+            amount,
+        } => spend_whale_cross_chain(deps, info, recipient_chain, recipient_address, amount),
+        ExecuteMsg::Receive(cw20_msg) => receive_cw20(deps, &env, info, cw20_msg),
+        ExecuteMsg::Withdraw { shares } => withdraw(deps, &env, info, shares),
         ExecuteMsg::SetAdmin { admin } => {
             let admin_addr = deps.api.addr_validate(&admin)?;
             let previous_admin = ADMIN.get(deps.as_ref())?.unwrap();
@@ -56,9 +70,36 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> C
                 .add_attribute("previous admin", previous_admin)
                 .add_attribute("admin", admin))
         }
+        ExecuteMsg::RecordPrice { pool } => record_price(deps, &env, pool),
     }
 }
 
+// Records a WHALE/UST TWAP observation for `pool`, so spend/grant valuation can use a
+// manipulation-resistant price instead of the instantaneous, flash-loan-skewable
+// `pool_ratio`. Anyone may call this -- it only ever appends a new observation, it
+// doesn't move funds.
+pub fn record_price(deps: DepsMut, env: &Env, pool: String) -> CommunityFundResult {
+    let state = STATE.load(deps.storage)?;
+    let pool_address = deps.api.addr_validate(&pool)?;
+    let whale_token_addr = deps.api.addr_humanize(&state.whale_token_addr)?;
+
+    let observation = record_price_observation(
+        deps,
+        env,
+        pool_address,
+        AssetInfo::Token {
+            contract_addr: whale_token_addr.to_string(),
+        },
+        AssetInfo::NativeToken {
+            denom: UST_DENOM.to_string(),
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("action", "record_price")
+        .add_attribute("cumulative_price", observation.cumulative_price.to_string()))
+}
+
 // Transfer WHALE to specified recipient
 pub fn spend_whale(
     deps: Deps,
@@ -91,36 +132,237 @@ pub fn burn_whale(deps: Deps, info: MessageInfo, amount: Uint128) -> CommunityFu
     )
 }
 
-// Deposits WHALE tokens into the contract
-pub fn deposit(deps: DepsMut, env: &Env, msg_info: MessageInfo) -> CommunityFundResult {
-    if msg_info.funds.len() > 1 {
-        return Err(CommunityFundError::WrongDepositTooManyTokens {});
-    } else if msg_info.funds.first()?.denom != WHALE_DENOM {
+// Routes a grant to a recipient on another Cosmos/EVM chain through a Wormhole-style
+// token bridge: approve the bridge for the grant amount, then dispatch its
+// InitiateTransfer message carrying the zero-padded recipient address.
+pub fn spend_whale_cross_chain(
+    deps: DepsMut,
+    info: MessageInfo,
+    recipient_chain: u16,
+    recipient_address: String,
+    amount: Uint128,
+) -> CommunityFundResult {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    let state = STATE.load(deps.storage)?;
+    let whale_token_addr = deps.api.addr_humanize(&state.whale_token_addr)?;
+    let token_bridge_addr = deps.api.addr_humanize(&state.token_bridge_addr)?;
+
+    let increase_allowance_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: whale_token_addr.to_string(),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::IncreaseAllowance {
+            spender: token_bridge_addr.to_string(),
+            amount,
+            expires: None,
+        })?,
+    });
+
+    let recipient = pad_recipient_address(&recipient_address)?;
+
+    let initiate_transfer_msg = SubMsg {
+        id: TOKEN_BRIDGE_TRANSFER_REPLY_ID,
+        gas_limit: None,
+        reply_on: ReplyOn::Success,
+        msg: CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: token_bridge_addr.to_string(),
+            funds: vec![],
+            msg: to_binary(&TokenBridgeExecuteMsg::InitiateTransfer {
+                asset: TransferInfo {
+                    amount,
+                    token_address: whale_token_addr.to_string(),
+                    token_chain: 0,
+                    recipient,
+                    recipient_chain,
+                    fee: Uint128::zero(),
+                },
+                nonce: 0,
+            })?,
+        }),
+    };
+
+    Ok(Response::new()
+        .add_message(increase_allowance_msg)
+        .add_submessage(initiate_transfer_msg)
+        .add_attribute("action", "spend_whale_cross_chain")
+        .add_attribute("recipient_chain", recipient_chain.to_string())
+        .add_attribute("amount", amount))
+}
+
+// Zero-pads the recipient's raw foreign-chain address bytes to the 32 bytes expected by
+// the token bridge. `recipient_address` is a Wormhole recipient on some *other* chain, not
+// an address on this one, so `addr_canonicalize` (which only decodes this chain's own
+// bech32 address space) is the wrong tool here -- the bytes are taken as-is.
+fn pad_recipient_address(recipient_address: &str) -> StdResult<Binary> {
+    let raw = recipient_address.as_bytes();
+    if raw.len() > 32 {
+        return Err(StdError::generic_err(
+            "recipient address exceeds 32 bytes",
+        ));
+    }
+    let mut padded = vec![0u8; 32 - raw.len()];
+    padded.extend_from_slice(raw);
+    Ok(Binary::from(padded))
+}
+
+pub fn reply(_deps: DepsMut, _env: Env, msg: Reply) -> CommunityFundResult {
+    match msg.id {
+        TOKEN_BRIDGE_TRANSFER_REPLY_ID => {
+            let sequence = msg
+                .result
+                .into_result()
+                .map_err(StdError::generic_err)?
+                .events
+                .iter()
+                .find(|event| event.ty == "wasm")
+                .and_then(|event| event.attributes.iter().find(|attr| attr.key == "sequence"))
+                .map(|attr| attr.value.clone())
+                .unwrap_or_default();
+
+            Ok(Response::new()
+                .add_attribute("action", "spend_whale_cross_chain_reply")
+                .add_attribute("sequence", sequence))
+        }
+        id => Err(CommunityFundError::Std(StdError::generic_err(format!(
+            "Unknown reply id: {}",
+            id
+        )))),
+    }
+}
+
+// Handles WHALE cw20 deposits sent via `Cw20ExecuteMsg::Send`, crediting the
+// sender with vault shares in a single transaction (no prior allowance needed).
+pub fn receive_cw20(
+    deps: DepsMut,
+    env: &Env,
+    msg_info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> CommunityFundResult {
+    let state = STATE.load(deps.storage)?;
+    if deps.api.addr_validate(&msg_info.sender.to_string())?
+        != deps.api.addr_humanize(&state.whale_token_addr)?
+    {
         return Err(CommunityFundError::WrongDepositToken {});
     }
 
-    let mut state = STATE.load(deps.storage)?;
+    match from_binary(&cw20_msg.msg)? {
+        Cw20HookMsg::Deposit {} => deposit(deps, env, state, cw20_msg.sender, cw20_msg.amount),
+    }
+}
+
+// Mints vault shares for a deposit of `amount` WHALE already transferred into the contract.
+fn deposit(
+    deps: DepsMut,
+    env: &Env,
+    state: State,
+    depositor: String,
+    amount: Uint128,
+) -> CommunityFundResult {
+    // The cw20 tokens are already transferred to the contract by the time `Receive` fires,
+    // so the balance before the deposit is the current balance minus the incoming amount.
+    let whale_token_addr = deps.api.addr_humanize(&state.whale_token_addr)?;
+    let current_balance =
+        query_token_balance(&deps.querier, whale_token_addr, env.contract.address.clone())?;
+    let balance_before = current_balance.checked_sub(amount)?;
+
+    let depositor_addr = deps.api.addr_validate(&depositor)?;
+    let shares = mint_shares(deps, &depositor_addr, amount, balance_before)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "deposit")
+        .add_attribute("shares_minted", shares))
+}
+
+// Computes and credits the shares owed for a deposit of `amount` WHALE, given the
+// fund's WHALE balance measured before the incoming transfer lands.
+fn mint_shares(
+    deps: DepsMut,
+    depositor: &cosmwasm_std::Addr,
+    amount: Uint128,
+    balance_before: Uint128,
+) -> Result<Uint128, CommunityFundError> {
+    let total_supply = TOTAL_SUPPLY.load(deps.storage)?;
+
+    let shares = if total_supply.is_zero() {
+        amount
+    } else {
+        // `spend_whale`/`burn_whale` can drain the fund's WHALE balance to zero while
+        // TOTAL_SUPPLY stays nonzero; guard the ratio instead of panicking on
+        // divide-by-zero inside `multiply_ratio`.
+        if balance_before.is_zero() {
+            return Err(CommunityFundError::ZeroShareMint {});
+        }
+        amount.multiply_ratio(total_supply, balance_before)
+    };
+
+    if shares.is_zero() {
+        return Err(CommunityFundError::ZeroShareMint {});
+    }
+
+    BALANCE_OF.update(
+        deps.storage,
+        depositor,
+        |balance| -> StdResult<Uint128> { Ok(balance.unwrap_or_default() + shares) },
+    )?;
+    TOTAL_SUPPLY.save(deps.storage, &(total_supply + shares))?;
+
+    Ok(shares)
+}
+
+// Redeems `shares` vault shares for their proportional amount of the fund's WHALE balance
+pub fn withdraw(deps: DepsMut, env: &Env, msg_info: MessageInfo, shares: Uint128) -> CommunityFundResult {
+    let state = STATE.load(deps.storage)?;
+    let total_supply = TOTAL_SUPPLY.load(deps.storage)?;
+
+    if total_supply.is_zero() {
+        return Err(CommunityFundError::ZeroShareMint {});
+    }
+
+    let balance = BALANCE_OF
+        .may_load(deps.storage, &msg_info.sender)?
+        .unwrap_or_default();
+    if shares > balance {
+        return Err(CommunityFundError::InsufficientShares {});
+    }
+
+    let whale_token_addr = deps.api.addr_humanize(&state.whale_token_addr)?;
+    let fund_balance = query_token_balance(&deps.querier, whale_token_addr.clone(), env.contract.address.clone())?;
+    let amount = shares.multiply_ratio(fund_balance, total_supply);
+
+    BALANCE_OF.save(deps.storage, &msg_info.sender, &(balance - shares))?;
+    TOTAL_SUPPLY.save(deps.storage, &(total_supply - shares))?;
 
     let msg = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: state.whale_token_addr.to_string(),
+        contract_addr: whale_token_addr.to_string(),
         funds: vec![],
-        msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
-            owner: msg_info.sender.to_string(),
-            recipient: env.contract.address.to_string(),
-            amount: msg_info.funds.first()?.amount,
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: msg_info.sender.to_string(),
+            amount,
         })?,
     });
 
-    Ok(Response::new().add_message(msg))
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("action", "withdraw")
+        .add_attribute("shares_burned", shares)
+        .add_attribute("amount", amount))
 }
 
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Admin {} => Ok(to_binary(&ADMIN.query_admin(deps)?)?),
         QueryMsg::Config {} => query_config(deps),
+        QueryMsg::Shares { address } => query_shares(deps, address),
     }
 }
 
+pub fn query_shares(deps: Deps, address: String) -> StdResult<Binary> {
+    let validated = deps.api.addr_validate(&address)?;
+    let shares = BALANCE_OF
+        .may_load(deps.storage, &validated)?
+        .unwrap_or_default();
+    to_binary(&shares)
+}
+
 pub fn query_config(deps: Deps) -> StdResult<Binary> {
     let state = STATE.load(deps.storage)?;
     to_binary(&ConfigResponse {
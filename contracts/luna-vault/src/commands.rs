@@ -1,6 +1,6 @@
 use std::borrow::BorrowMut;
 
-use cosmwasm_std::{Api, attr, BankMsg, Coin, coins, CosmosMsg, Decimal, DepsMut, Env, from_binary, MessageInfo, Response, StdError, StdResult, Storage, to_binary, Uint128, WasmMsg};
+use cosmwasm_std::{Addr, Api, attr, BankMsg, Coin, coins, CosmosMsg, Decimal, Deps, DepsMut, Env, from_binary, MessageInfo, Response, StdError, StdResult, Storage, to_binary, Uint128, WasmMsg};
 use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
 use terraswap::asset::{Asset, AssetInfo};
 use terraswap::querier::query_supply;
@@ -8,16 +8,34 @@ use terraswap::querier::query_supply;
 use signed_integer::SignedInt;
 use white_whale::anchor::anchor_deposit_msg;
 use white_whale::astroport_helper::{create_astroport_lp_msg, create_astroport_msg};
+use white_whale::denom::LUNA_DENOM;
 use white_whale::fee::Fee;
 use white_whale::luna_vault::msg::Cw20HookMsg;
 use white_whale::memory::LIST_SIZE_LIMIT;
+use white_whale::query::balance::AssetQueryKind;
+use white_whale::query::terraswap::{query_pool, simulate_swap};
+use white_whale::treasury::dapp_base::common::{AdapterEntry, AdapterKind};
+use white_whale::treasury::dapp_base::state::ADDRESS_BOOK;
 
 use crate::contract::VaultResult;
 use crate::error::LunaVaultError;
 use crate::helpers::{check_fee, compute_total_value, get_treasury_fee, slashing};
 use crate::math::decimal_division;
 use crate::pool_info::PoolInfoRaw;
-use crate::state::{ADMIN, CURRENT_BATCH, DEPOSIT_INFO, FEE, get_finished_amount, get_unbond_batches, PARAMETERS, POOL_INFO, PROFIT, read_unbond_history, remove_unbond_wait_list, STATE, State, store_unbond_history, store_unbond_wait_list, UnbondHistory};
+use crate::state::{
+    ADMIN, BLOCK_LOAN_USAGE, BOND_CHECKPOINTS, BOND_REWARD_PAGE_LIMIT, BONDS, BlockLoanUsage,
+    CURRENT_BATCH, COST_MODEL, DEPOSIT_INFO, EPOCH_PROFITS, EpochWithdrawals, FEE, Bond,
+    FEE_LEDGER, FEE_TOTALS, FeeCurve, FeeRecord, FeeTotals, GLOBAL, GLOBAL_CHECKPOINTS,
+    GlobalIndex, get_finished_amount, get_unbond_batches, LP_SHARES, MAX_LOAN_DEPTH, PARAMETERS,
+    PASSIVE_STRATEGY_IDLE_BUFFER, POOL_INFO, PROFIT, read_unbond_history,
+    remove_unbond_wait_list, STATE, State, store_unbond_history, store_unbond_wait_list,
+    TOTAL_LP_SHARES, UnbondHistory, WHITELIST, WhitelistEntry,
+    WITHDRAWAL_LIMIT, WITHDRAWN_THIS_EPOCH,
+};
+
+/// Decimals of the underlying unbonding coin (LUNA, like all native Terra denoms, is
+/// denominated in micro units on-chain).
+const UNDERLYING_COIN_DECIMALS: u32 = 6;
 
 /// handler function invoked when the luna-vault contract receives
 /// a transaction. In this case it is triggered when the LP tokens are deposited
@@ -37,10 +55,60 @@ pub fn receive_cw20(
             }
             unbond(deps, env, cw20_msg.amount, cw20_msg.sender)
         }
+        Cw20HookMsg::Bond {} => {
+            // only vLuna token contract can execute this message
+            let info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
+            if deps.api.addr_validate(&msg_info.sender.to_string())? != info.liquidity_token {
+                return Err(LunaVaultError::Unauthorized {});
+            }
+            bond(deps, env, cw20_msg.sender, cw20_msg.amount)
+        }
     }
 }
 
 
+// Mints `amount` of the parallel LP-share ledger to `holder`, alongside the vluna the
+// holder is minted in the same call. This ledger exists purely so flash-loan profit can
+// be valued per-holder against `total_value` (which includes accrued trading profit and
+// passive-strategy yield), distinct from `state.exchange_rate`, which only tracks the
+// peg-stability-oriented bonded/supply ratio.
+fn mint_lp_shares(storage: &mut dyn Storage, holder: &Addr, amount: Uint128) -> StdResult<()> {
+    let shares = LP_SHARES.may_load(storage, holder)?.unwrap_or_default() + amount;
+    LP_SHARES.save(storage, holder, &shares)?;
+    let total = TOTAL_LP_SHARES.may_load(storage)?.unwrap_or_default() + amount;
+    TOTAL_LP_SHARES.save(storage, &total)?;
+    Ok(())
+}
+
+// Burns `amount` of the parallel LP-share ledger from `holder`. See `mint_lp_shares`.
+fn burn_lp_shares(storage: &mut dyn Storage, holder: &Addr, amount: Uint128) -> StdResult<()> {
+    let shares = LP_SHARES.may_load(storage, holder)?.unwrap_or_default();
+    LP_SHARES.save(storage, holder, &(shares.checked_sub(amount))?)?;
+    let total = TOTAL_LP_SHARES.may_load(storage)?.unwrap_or_default();
+    TOTAL_LP_SHARES.save(storage, &(total.checked_sub(amount))?)?;
+    Ok(())
+}
+
+/// Returns `holder`'s LP-share balance and its current Luna-equivalent value, i.e. the
+/// holder's pro-rata slice of `total_value` (including accrued flash-loan profit and
+/// passive-strategy yield), not just the peg-tracking exchange rate used for deposits
+/// and withdrawals.
+pub fn query_lp_share_value(deps: Deps, env: Env, holder: String) -> StdResult<(Uint128, Uint128)> {
+    let holder_addr = deps.api.addr_validate(&holder)?;
+    let shares = LP_SHARES.may_load(deps.storage, &holder_addr)?.unwrap_or_default();
+    let total_shares = TOTAL_LP_SHARES.may_load(deps.storage)?.unwrap_or_default();
+
+    if total_shares.is_zero() {
+        return Ok((shares, Uint128::zero()));
+    }
+
+    let pool_info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
+    let (total_value, _, _, _, _) = compute_total_value(&env, deps, &pool_info)?;
+    let value = shares.multiply_ratio(total_value, total_shares);
+
+    Ok((shares, value))
+}
+
 // Deposits Luna into the contract.
 pub fn provide_liquidity(
     mut deps: DepsMut,
@@ -52,7 +120,7 @@ pub fn provide_liquidity(
     let profit = PROFIT.load(deps.storage)?;
     let info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
 
-    if profit.last_balance != Uint128::zero() {
+    if profit.depth != 0 {
         return Err(LunaVaultError::DepositDuringLoan {});
     }
 
@@ -85,14 +153,27 @@ pub fn provide_liquidity(
     // peg recovery fee should be considered
     let mint_amount = decimal_division(deposit, state.exchange_rate);
     let mut mint_amount_with_fee = mint_amount;
+    let mut peg_fee = Uint128::zero();
     if state.exchange_rate < threshold {
         let max_peg_fee = mint_amount * recovery_fee;
         let required_peg_fee = ((total_supply + mint_amount + current_batch.requested_with_fee)
             .checked_sub(state.total_bond_amount + deposit))?;
-        let peg_fee = Uint128::min(max_peg_fee, required_peg_fee);
+        peg_fee = Uint128::min(max_peg_fee, required_peg_fee);
         mint_amount_with_fee = (mint_amount.checked_sub(peg_fee))?;
     }
 
+    if !peg_fee.is_zero() {
+        record_fee(
+            deps.storage,
+            current_batch.id,
+            env.block.time.seconds(),
+            state.exchange_rate,
+            Uint128::zero(),
+            peg_fee,
+            Uint128::zero(),
+        )?;
+    }
+
     // total supply should be updated for exchange rate calculation.
     total_supply += mint_amount_with_fee;
 
@@ -101,6 +182,11 @@ pub fn provide_liquidity(
     state.update_exchange_rate(total_supply, requested_with_fee);
     STATE.save(deps.storage, &state)?;
 
+    // Mirror the vluna mint 1:1 into the LP-share ledger. The shares are priced at
+    // `state.exchange_rate` like the vluna itself, not against `total_value`, so accrued
+    // flash-loan profit does not raise the price a later depositor pays per share.
+    mint_lp_shares(deps.storage, &msg_info.sender, mint_amount_with_fee)?;
+
     // mint LP token to sender
     let msg = CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: info.liquidity_token.to_string(),
@@ -112,20 +198,125 @@ pub fn provide_liquidity(
     });
 
     let response = Response::new().add_attributes(attrs).add_message(msg);
-    // If contract holds more than ASTROPORT_DEPOSIT_THRESHOLD [LUNA] then try deposit to Astroport and leave LUNA_CAP [LUNA] in contract.
+    // If contract holds more than luna_cap [LUNA] then try deposit the excess into
+    // Astroport and leave luna_cap [LUNA] liquid in the contract.
     let (_, luna_in_contract, _, _, _) = compute_total_value(&env, deps.as_ref(), &info)?;
     return if luna_in_contract > info.luna_cap {
-        _deposit_passive_strategy(response)
+        let excess = (luna_in_contract.checked_sub(info.luna_cap))?;
+        _deposit_passive_strategy(deps.as_ref(), &state.bluna_address, &info, excess, response)
     } else {
         Ok(response)
     };
 }
 
-// Deposits Luna into the passive strategy (Astroport) -> luna-bluna LP
-fn _deposit_passive_strategy(response: Response) -> VaultResult {
-    //let deposit_msg = create_astroport_lp_msg();
-    //Ok(response.add_message(deposit_msg))
-    Ok(response)
+// Deposits `amount` LUNA into the Astroport luna-bLuna LP: half is swapped into bLuna so
+// the position can be provided as a balanced pair, the other half is provided alongside it.
+fn _deposit_passive_strategy(
+    deps: Deps,
+    bluna_address: &cosmwasm_std::Addr,
+    info: &PoolInfoRaw,
+    amount: Uint128,
+    response: Response,
+) -> VaultResult {
+    if amount.is_zero() {
+        return Ok(response);
+    }
+
+    let to_swap = amount / Uint128::from(2u128);
+    let to_provide = (amount.checked_sub(to_swap))?;
+
+    let swap_coin = Coin {
+        denom: LUNA_DENOM.to_string(),
+        amount: to_swap,
+    };
+    let expected_bluna = simulate_swap(deps, info.astro_pair_address.clone(), swap_coin.clone())?;
+
+    let swap_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: info.astro_pair_address.to_string(),
+        funds: coins(to_swap.u128(), LUNA_DENOM),
+        msg: to_binary(&create_astroport_msg(swap_coin, None, None))?,
+    });
+
+    let provide_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: info.astro_pair_address.to_string(),
+        funds: coins(to_provide.u128(), LUNA_DENOM),
+        msg: to_binary(&create_astroport_lp_msg(
+            [
+                Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: LUNA_DENOM.to_string(),
+                    },
+                    amount: to_provide,
+                },
+                Asset {
+                    info: AssetInfo::Token {
+                        contract_addr: bluna_address.to_string(),
+                    },
+                    amount: expected_bluna,
+                },
+            ],
+            None,
+        ))?,
+    });
+
+    Ok(response
+        .add_message(swap_msg)
+        .add_message(provide_msg)
+        .add_attribute("action", "deposit_passive_strategy")
+        .add_attribute("luna_deposited", amount))
+}
+
+// Pulls `shortfall` worth of LUNA back out of the Astroport luna-bLuna LP by withdrawing
+// a proportional amount of LP tokens, using the pool's current ratio as an estimate.
+fn _withdraw_from_passive_strategy(
+    deps: Deps,
+    info: &PoolInfoRaw,
+    shortfall: Uint128,
+) -> VaultResult {
+    let pool = query_pool(deps, info.astro_pair_address.clone())?;
+    let luna_reserves = pool
+        .assets
+        .iter()
+        .find(|a| a.info == AssetInfo::NativeToken { denom: LUNA_DENOM.to_string() })
+        .ok_or_else(|| StdError::generic_err("LUNA not found in Astroport pool"))?
+        .amount;
+
+    let lp_amount = shortfall.multiply_ratio(pool.total_share, luna_reserves);
+
+    let withdraw_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: info.astro_lp_token.to_string(),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::Send {
+            contract: info.astro_pair_address.to_string(),
+            amount: lp_amount,
+            msg: to_binary(&terraswap::pair::Cw20HookMsg::WithdrawLiquidity {})?,
+        })?,
+    });
+
+    Ok(Response::new()
+        .add_message(withdraw_msg)
+        .add_attribute("action", "withdraw_from_passive_strategy")
+        .add_attribute("luna_requested", shortfall))
+}
+
+/// Tops up or unwinds the Astroport passive position so liquid LUNA held by the
+/// vault stays near `luna_cap`, letting idle capital earn yield without starving withdrawals.
+pub fn rebalance(deps: DepsMut, env: Env, msg_info: MessageInfo) -> VaultResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+
+    let info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    let (_, luna_in_contract, _, _, _) = compute_total_value(&env, deps.as_ref(), &info)?;
+
+    if luna_in_contract > info.luna_cap {
+        let excess = (luna_in_contract.checked_sub(info.luna_cap))?;
+        _deposit_passive_strategy(deps.as_ref(), &state.bluna_address, &info, excess, Response::new())
+    } else if luna_in_contract < info.luna_cap {
+        let shortfall = (info.luna_cap.checked_sub(luna_in_contract))?;
+        _withdraw_from_passive_strategy(deps.as_ref(), &info, shortfall)
+    } else {
+        Ok(Response::new().add_attribute("action", "rebalance_noop"))
+    }
 }
 
 /// This message must be called by receive_cw20
@@ -137,7 +328,7 @@ fn unbond(
     sender: String, // human who sent the vluna to us
 ) -> VaultResult {
     let profit = PROFIT.load(deps.storage)?;
-    if profit.last_balance != Uint128::zero() {
+    if profit.depth != 0 {
         return Err(LunaVaultError::DepositDuringLoan {});
     }
 
@@ -147,6 +338,7 @@ fn unbond(
     attrs.push(("burnt_amount", amount.to_string()));
 
     let mut current_batch = CURRENT_BATCH.load(deps.storage)?;
+    let batch_id_for_fee = current_batch.id;
 
     // Check slashing, update state, and calculate the new exchange rate.
     let params = PARAMETERS.load(deps.storage)?;
@@ -244,6 +436,13 @@ fn unbond(
 
     // Construct treasury fee msg.
     let fee_config = FEE.load(deps.storage)?;
+
+    // Mirror the vluna burn into the LP-share ledger: the sender gives up `amount`
+    // shares, and the treasury keeps what it retained in `treasury_fee` instead of
+    // burning it, matching the actual vluna flows below.
+    burn_lp_shares(deps.storage, &sender_addr, amount)?;
+    mint_lp_shares(deps.storage, &fee_config.treasury_addr, treasury_fee)?;
+
     let treasury_fee_msg = fee_config.treasury_fee.msg(
         deps.as_ref(),
         lp_token_treasury_fee,
@@ -251,6 +450,20 @@ fn unbond(
     )?;
     attrs.push(("Treasury fee:", treasury_fee.to_string()));
 
+    // Whatever `amount_with_fee` lost beyond the treasury fee is the peg-recovery fee.
+    let unbond_peg_fee = amount
+        .checked_sub(treasury_fee)?
+        .checked_sub(amount_with_fee)?;
+    record_fee(
+        deps.storage,
+        batch_id_for_fee,
+        env.block.time.seconds(),
+        state.exchange_rate,
+        treasury_fee,
+        unbond_peg_fee,
+        Uint128::zero(),
+    )?;
+
     // Send Burn message to vluna contract
     let burn_msg = CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: info.liquidity_token.to_string(),
@@ -291,6 +504,8 @@ pub fn execute_withdraw_unbonded(
         return Err(LunaVaultError::NoWithdrawableAssetsAvailable(coin_denom));
     }
 
+    consume_withdrawal_budget(deps.storage, &env, params.epoch_period, withdraw_amount)?;
+
     // remove the previous batches for the user
     let deprecated_batches = get_unbond_batches(deps.storage, &info.sender, None)?;
     remove_unbond_wait_list(deps.storage, deprecated_batches, &info.sender)?;
@@ -308,13 +523,21 @@ pub fn execute_withdraw_unbonded(
         amount: coins(withdraw_amount.u128(), &*coin_denom),
     });
 
-    Ok(Response::new()
-        .add_attributes(vec![
-            attr("action", "execute_withdraw_unbonded"),
-            attr("from", env.contract.address),
-            attr("amount", withdraw_amount),
-        ])
-        .add_message(withdraw_msg))
+    let mut response = Response::new().add_attributes(vec![
+        attr("action", "execute_withdraw_unbonded"),
+        attr("from", env.contract.address.clone()),
+        attr("amount", withdraw_amount),
+    ]);
+
+    // Pull LUNA back out of Astroport if liquid vault balance can't cover this withdrawal.
+    if vault_balance < withdraw_amount {
+        let pool_info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
+        let shortfall = (withdraw_amount.checked_sub(vault_balance))?;
+        let passive_withdrawal = _withdraw_from_passive_strategy(deps.as_ref(), &pool_info, shortfall)?;
+        response = response.add_submessages(passive_withdrawal.messages);
+    }
+
+    Ok(response.add_message(withdraw_msg))
 }
 
 /// This is designed for an accurate unbonded amount calculation.
@@ -429,6 +652,357 @@ fn _process_withdraw_rate(
     Ok(())
 }
 
+//----------------------------------------------------------------------------------------
+//  FEE LEDGER
+//----------------------------------------------------------------------------------------
+
+/// Records fees actually collected for `batch_id`, adding to any fees already recorded
+/// for that batch, and folds the same amounts into the running cumulative totals. Called
+/// everywhere a fee is taken so the ledger stays a complete, queryable revenue trail.
+pub(crate) fn record_fee(
+    storage: &mut dyn Storage,
+    batch_id: u64,
+    time: u64,
+    exchange_rate: Decimal,
+    treasury_fee: Uint128,
+    peg_fee: Uint128,
+    flash_loan_fee: Uint128,
+) -> StdResult<()> {
+    FEE_LEDGER.update(storage, batch_id, |existing| -> StdResult<FeeRecord> {
+        let mut record = existing.unwrap_or(FeeRecord {
+            treasury_fee: Uint128::zero(),
+            peg_fee: Uint128::zero(),
+            flash_loan_fee: Uint128::zero(),
+            exchange_rate,
+            time,
+        });
+        record.treasury_fee += treasury_fee;
+        record.peg_fee += peg_fee;
+        record.flash_loan_fee += flash_loan_fee;
+        record.exchange_rate = exchange_rate;
+        record.time = time;
+        Ok(record)
+    })?;
+
+    let mut totals = FEE_TOTALS.may_load(storage)?.unwrap_or(FeeTotals {
+        treasury_fee: Uint128::zero(),
+        peg_fee: Uint128::zero(),
+        flash_loan_fee: Uint128::zero(),
+    });
+    totals.treasury_fee += treasury_fee;
+    totals.peg_fee += peg_fee;
+    totals.flash_loan_fee += flash_loan_fee;
+    FEE_TOTALS.save(storage, &totals)?;
+
+    Ok(())
+}
+
+/// Returns the recorded fee breakdown for a single batch.
+pub fn query_fees_by_batch(deps: Deps, batch_id: u64) -> StdResult<FeeRecord> {
+    FEE_LEDGER.load(deps.storage, batch_id)
+}
+
+/// Returns the all-time cumulative fee breakdown.
+pub fn query_total_fees_collected(deps: Deps) -> StdResult<FeeTotals> {
+    Ok(FEE_TOTALS.may_load(deps.storage)?.unwrap_or(FeeTotals {
+        treasury_fee: Uint128::zero(),
+        peg_fee: Uint128::zero(),
+        flash_loan_fee: Uint128::zero(),
+    }))
+}
+
+//----------------------------------------------------------------------------------------
+//  FLASH-LOAN COST MODEL
+//----------------------------------------------------------------------------------------
+//
+// Flat-percentage flash-loan fees ignore how much of the vault's liquidity a single
+// loan consumes. When a fee curve is configured, the effective rate follows a kinked
+// utilization curve like a money-market interest model: `utilization = loan_amount /
+// total_value`, rate is `base_rate + slope1 * utilization` below `optimal_utilization`,
+// and `base_rate + slope1 * optimal_utilization + slope2 * (utilization - optimal)`
+// above it, so loans that drain a large share of the vault pay progressively more.
+// Until a curve is configured, `FEE.flash_loan_fee`'s flat percentage is used as-is. A
+// per-block aggregate budget separately caps how much can be loaned out per block.
+
+/// Sets the kinked utilization fee curve. Passing `None` clears the curve and falls
+/// back to the flat `FEE.flash_loan_fee` percentage. Admin-only.
+pub fn set_flash_loan_fee_curve(
+    deps: DepsMut,
+    msg_info: MessageInfo,
+    curve: Option<FeeCurve>,
+) -> VaultResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+
+    let mut model = COST_MODEL.may_load(deps.storage)?.unwrap_or_default();
+    model.fee_curve = curve.clone();
+    COST_MODEL.save(deps.storage, &model)?;
+
+    let mut response = Response::new().add_attribute("action", "set_flash_loan_fee_curve");
+    if let Some(curve) = curve {
+        response = response
+            .add_attribute("base_rate", curve.base_rate.to_string())
+            .add_attribute("slope1", curve.slope1.to_string())
+            .add_attribute("slope2", curve.slope2.to_string())
+            .add_attribute("optimal_utilization", curve.optimal_utilization.to_string());
+    } else {
+        response = response.add_attribute("fee_curve", "cleared");
+    }
+    Ok(response)
+}
+
+/// Sets the per-block aggregate loaned-out budget (zero disables the guardrail).
+/// Admin-only.
+pub fn set_flash_loan_block_budget(
+    deps: DepsMut,
+    msg_info: MessageInfo,
+    per_block_budget: Uint128,
+) -> VaultResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+
+    let mut model = COST_MODEL.may_load(deps.storage)?.unwrap_or_default();
+    model.per_block_budget = per_block_budget;
+    COST_MODEL.save(deps.storage, &model)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_flash_loan_block_budget")
+        .add_attribute("per_block_budget", per_block_budget))
+}
+
+/// Computes the effective fee for borrowing `loan_amount` out of `total_value` under
+/// the configured cost model, along with the resolved rate and utilization so callers
+/// can surface them as response attributes. Falls back to the flat `FEE.flash_loan_fee`
+/// percentage when no curve is configured.
+pub(crate) fn compute_flash_loan_fee(
+    storage: &dyn Storage,
+    loan_amount: Uint128,
+    total_value: Uint128,
+) -> StdResult<(Uint128, Decimal, Decimal)> {
+    let model = COST_MODEL.may_load(storage)?.unwrap_or_default();
+
+    let utilization = if total_value.is_zero() {
+        Decimal::one()
+    } else {
+        Decimal::from_ratio(loan_amount, total_value)
+    };
+
+    match model.fee_curve {
+        Some(curve) => {
+            let rate = if utilization <= curve.optimal_utilization {
+                curve.base_rate + curve.slope1 * utilization
+            } else {
+                let excess_utilization = utilization - curve.optimal_utilization;
+                curve.base_rate
+                    + curve.slope1 * curve.optimal_utilization
+                    + curve.slope2 * excess_utilization
+            };
+            Ok((loan_amount * rate, rate, utilization))
+        }
+        None => {
+            let fees = FEE.load(storage)?;
+            let fee = fees.flash_loan_fee.compute(loan_amount);
+            let rate = if loan_amount.is_zero() {
+                Decimal::zero()
+            } else {
+                Decimal::from_ratio(fee, loan_amount)
+            };
+            Ok((fee, rate, utilization))
+        }
+    }
+}
+
+/// Quotes the fee a hypothetical loan of `loan_amount` would incur right now, so
+/// integrators can price round-trips before submitting. Returns `(fee, resolved_rate)`.
+pub fn query_flash_loan_fee_quote(
+    deps: Deps,
+    env: Env,
+    loan_amount: Uint128,
+) -> StdResult<(Uint128, Decimal)> {
+    let pool_info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
+    let (total_value, _, _, _, _) = compute_total_value(&env, deps, &pool_info)?;
+    let (fee, rate, _) = compute_flash_loan_fee(deps.storage, loan_amount, total_value)?;
+    Ok((fee, rate))
+}
+
+/// Reserves `amount` against the per-block aggregate loaned-out budget, resetting the
+/// counter whenever the block height has advanced. A zero `per_block_budget` is
+/// treated as "no limit configured" so this is a no-op until an admin sets one.
+pub(crate) fn reserve_block_loan_budget(
+    storage: &mut dyn Storage,
+    env: &Env,
+    amount: Uint128,
+) -> Result<(), LunaVaultError> {
+    let model = COST_MODEL.may_load(storage)?.unwrap_or_default();
+    if model.per_block_budget.is_zero() {
+        return Ok(());
+    }
+
+    let mut usage = BLOCK_LOAN_USAGE.may_load(storage)?.unwrap_or(BlockLoanUsage {
+        height: env.block.height,
+        loaned: Uint128::zero(),
+    });
+
+    if usage.height < env.block.height {
+        usage.height = env.block.height;
+        usage.loaned = Uint128::zero();
+    }
+
+    let loaned = usage.loaned + amount;
+    if loaned > model.per_block_budget {
+        return Err(LunaVaultError::PerBlockLoanBudgetExceeded {});
+    }
+    usage.loaned = loaned;
+    BLOCK_LOAN_USAGE.save(storage, &usage)?;
+
+    Ok(())
+}
+
+//----------------------------------------------------------------------------------------
+//  NESTED LOAN DEPTH
+//----------------------------------------------------------------------------------------
+//
+// A borrower's callback is allowed to re-borrow from the vault (recursive arbitrage),
+// tracked as a loan-depth counter in `PROFIT` instead of the old boolean-style latch.
+// This caps how deep that nesting can go, bounding gas and preventing griefing via
+// unbounded recursive flash loans.
+
+/// Sets the maximum nested flash-loan depth. Admin-only.
+pub fn set_max_loan_depth(deps: DepsMut, msg_info: MessageInfo, max_depth: u32) -> VaultResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+
+    MAX_LOAN_DEPTH.save(deps.storage, &max_depth)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_max_loan_depth")
+        .add_attribute("max_depth", max_depth.to_string()))
+}
+
+/// Registers `address` as the contract resolved for `name` (e.g. `ANCHOR_MONEY_MARKET_ID`)
+/// by the passive-strategy adapter, as a typed `AdapterKind::MoneyMarket` entry in the
+/// dapp_base address book -- the same registry `resolve_money_market` reads from -- instead
+/// of the untyped, parallel `VAULT_ADDRESS_BOOK` this used to maintain. Admin-only.
+pub fn update_vault_address_book(
+    deps: DepsMut,
+    msg_info: MessageInfo,
+    name: String,
+    address: String,
+) -> VaultResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+
+    let validated = deps.api.addr_validate(&address)?;
+    ADDRESS_BOOK.save(
+        deps.storage,
+        name.as_str(),
+        &AdapterEntry {
+            kind: AdapterKind::MoneyMarket,
+            address: validated,
+            asset_kind: AssetQueryKind::Cw20,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_vault_address_book")
+        .add_attribute("name", name)
+        .add_attribute("address", address))
+}
+
+/// Sets the idle LUNA buffer left un-deposited after a profitable flash loan; any
+/// surplus above it is re-deposited into the passive strategy. Admin-only.
+pub fn set_passive_strategy_idle_buffer(
+    deps: DepsMut,
+    msg_info: MessageInfo,
+    idle_buffer: Uint128,
+) -> VaultResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+
+    PASSIVE_STRATEGY_IDLE_BUFFER.save(deps.storage, &idle_buffer)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_passive_strategy_idle_buffer")
+        .add_attribute("idle_buffer", idle_buffer))
+}
+
+//----------------------------------------------------------------------------------------
+//  WITHDRAWAL RATE LIMITING
+//----------------------------------------------------------------------------------------
+
+/// Sets the per-epoch withdrawal limit, expressed in whole units of `underlying_coin_denom`
+/// (e.g. `1000` for 1000 LUNA, not the micro-denominated amount). Admin-only.
+pub fn set_withdrawal_limit(
+    deps: DepsMut,
+    msg_info: MessageInfo,
+    limit: Uint128,
+) -> VaultResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+
+    let scaled_limit = limit * Uint128::new(10u128.pow(UNDERLYING_COIN_DECIMALS));
+    WITHDRAWAL_LIMIT.save(deps.storage, &scaled_limit)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_withdrawal_limit")
+        .add_attribute("withdrawal_limit", scaled_limit))
+}
+
+/// Reserves `amount` against the rolling per-epoch withdrawal budget, resetting the
+/// counter whenever `env.block.time` has crossed into a new `epoch_period`. An unset
+/// budget is treated as "no limit configured" and is a no-op, same as
+/// `reserve_block_loan_budget`'s zero-budget case -- otherwise every withdrawal would
+/// revert until an admin opts in by calling `set_withdrawal_limit`. Errors with
+/// `WithdrawalLimitExceeded` if a budget is configured and the withdrawal wouldn't fit.
+fn consume_withdrawal_budget(
+    storage: &mut dyn Storage,
+    env: &Env,
+    epoch_period: u64,
+    amount: Uint128,
+) -> Result<(), LunaVaultError> {
+    let limit = match WITHDRAWAL_LIMIT.may_load(storage)? {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    let epoch_start = (env.block.time.seconds() / epoch_period) * epoch_period;
+
+    let mut tracked = WITHDRAWN_THIS_EPOCH
+        .may_load(storage)?
+        .unwrap_or(EpochWithdrawals {
+            epoch_start,
+            withdrawn: Uint128::zero(),
+        });
+
+    if tracked.epoch_start < epoch_start {
+        tracked.epoch_start = epoch_start;
+        tracked.withdrawn = Uint128::zero();
+    }
+
+    let remaining = limit.checked_sub(tracked.withdrawn).unwrap_or_default();
+    if amount > remaining {
+        return Err(LunaVaultError::WithdrawalLimitExceeded {});
+    }
+
+    tracked.withdrawn += amount;
+    WITHDRAWN_THIS_EPOCH.save(storage, &tracked)?;
+
+    Ok(())
+}
+
+/// Returns the remaining withdrawal budget for the current epoch, for display in
+/// frontends. Returns the full configured limit if nothing has been withdrawn yet this
+/// epoch, or `Uint128::MAX` if no budget has been configured (unlimited), mirroring
+/// `consume_withdrawal_budget`'s no-op behavior in that case.
+pub fn query_withdrawal_budget_remaining(deps: Deps, env: Env, epoch_period: u64) -> StdResult<Uint128> {
+    let limit = match WITHDRAWAL_LIMIT.may_load(deps.storage)? {
+        Some(limit) => limit,
+        None => return Ok(Uint128::MAX),
+    };
+    let epoch_start = (env.block.time.seconds() / epoch_period) * epoch_period;
+
+    let tracked = WITHDRAWN_THIS_EPOCH.may_load(deps.storage)?;
+    let withdrawn = match tracked {
+        Some(tracked) if tracked.epoch_start >= epoch_start => tracked.withdrawn,
+        _ => Uint128::zero(),
+    };
+
+    Ok(limit.checked_sub(withdrawn).unwrap_or_default())
+}
+
 /// Sets the liquid luna cap on the vault.
 pub fn set_luna_cap(deps: DepsMut, msg_info: MessageInfo, luna_cap: Uint128) -> VaultResult {
     // Only the admin should be able to call this
@@ -479,40 +1053,53 @@ pub fn set_fee(
     Ok(Response::default())
 }
 
-/// Adds a contract to the whitelist
+/// Grants a contract a scoped flash-loan capability: a max notional per call, a
+/// cumulative cap across its lifetime, and an optional expiry block height. Calling
+/// this again for an already-whitelisted contract updates its existing entry.
 pub fn add_to_whitelist(
     deps: DepsMut,
     msg_info: MessageInfo,
     contract_addr: String,
+    max_per_call: Uint128,
+    cumulative_cap: Uint128,
+    expiry_height: Option<u64>,
 ) -> VaultResult {
     // Only the admin should be able to call this
     ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
 
-    let mut state = STATE.load(deps.storage)?;
-    // Check if contract is already in whitelist
-    if state
-        .whitelisted_contracts
-        .contains(&deps.api.addr_validate(&contract_addr)?)
-    {
-        return Err(LunaVaultError::AlreadyWhitelisted {});
-    }
+    let validated_addr = deps.api.addr_validate(&contract_addr)?;
+    let already_whitelisted = WHITELIST.has(deps.storage, &validated_addr);
 
     // This is a limit to prevent potentially running out of gas when doing lookups on the whitelist
-    if state.whitelisted_contracts.len() >= LIST_SIZE_LIMIT {
-        return Err(LunaVaultError::WhitelistLimitReached {});
+    if !already_whitelisted {
+        let entry_count = WHITELIST
+            .keys(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+            .count();
+        if entry_count >= LIST_SIZE_LIMIT {
+            return Err(LunaVaultError::WhitelistLimitReached {});
+        }
     }
 
-    // Add contract to whitelist.
-    state
-        .whitelisted_contracts
-        .push(deps.api.addr_validate(&contract_addr)?);
-    STATE.save(deps.storage, &state)?;
+    WHITELIST.save(
+        deps.storage,
+        &validated_addr,
+        &WhitelistEntry {
+            max_per_call,
+            cumulative_cap,
+            cumulative_used: Uint128::zero(),
+            expiry_height,
+        },
+    )?;
 
     // Respond and note the change
-    Ok(Response::new().add_attribute("Added contract to whitelist: ", contract_addr))
+    Ok(Response::new()
+        .add_attribute("action", "add_to_whitelist")
+        .add_attribute("contract", contract_addr)
+        .add_attribute("max_per_call", max_per_call)
+        .add_attribute("cumulative_cap", cumulative_cap))
 }
 
-/// Removes a contract from the whitelist
+/// Revokes a contract's flash-loan capability entirely.
 pub fn remove_from_whitelist(
     deps: DepsMut,
     msg_info: MessageInfo,
@@ -521,26 +1108,38 @@ pub fn remove_from_whitelist(
     // Only the admin should be able to call this
     ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
 
-    let mut state = STATE.load(deps.storage)?;
-    // Check if contract is in whitelist
-    if !state
-        .whitelisted_contracts
-        .contains(&deps.api.addr_validate(&contract_addr)?)
-    {
+    let validated_addr = deps.api.addr_validate(&contract_addr)?;
+    if !WHITELIST.has(deps.storage, &validated_addr) {
         return Err(LunaVaultError::NotWhitelisted {});
     }
-
-    // Remove contract from whitelist.
-    let contract_validated_addr = deps.api.addr_validate(&contract_addr)?;
-    state
-        .whitelisted_contracts
-        .retain(|addr| *addr != contract_validated_addr);
-    STATE.save(deps.storage, &state)?;
+    WHITELIST.remove(deps.storage, &validated_addr);
 
     // Respond and note the change
     Ok(Response::new().add_attribute("Removed contract from whitelist: ", contract_addr))
 }
 
+/// Enumerates every whitelist entry along with its remaining per-call and cumulative
+/// allowance. Entries past their `expiry_height` are omitted, mirroring how they're
+/// treated as non-whitelisted at flash-loan time.
+pub fn query_whitelist(deps: Deps, env: Env) -> StdResult<Vec<(Addr, WhitelistEntry)>> {
+    WHITELIST
+        .range(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+        .filter(|item| {
+            item.as_ref()
+                .map(|(_, entry)| !is_whitelist_entry_expired(entry, &env))
+                .unwrap_or(true)
+        })
+        .collect()
+}
+
+/// True if `entry`'s optional expiry height has been reached.
+pub(crate) fn is_whitelist_entry_expired(entry: &WhitelistEntry, env: &Env) -> bool {
+    entry
+        .expiry_height
+        .map(|height| env.block.height >= height)
+        .unwrap_or(false)
+}
+
 /// Updates the contract state
 pub fn update_state(
     deps: DepsMut,
@@ -568,4 +1167,224 @@ pub fn update_state(
 
     STATE.save(deps.storage, &state)?;
     Ok(Response::new().add_attribute("Update:", "Successful"))
+}
+
+//----------------------------------------------------------------------------------------
+//  EPOCH-WEIGHTED PROFIT SHARING
+//----------------------------------------------------------------------------------------
+//
+// vLuna holders may additionally bond their tokens into this vault to earn a pro-rata
+// share of whichever epoch's booked profit they were bonded through, mirroring White
+// Whale's bonding-manager weighting scheme: a bonder's weight grows by `amount` for
+// every epoch it stays bonded, and a settled epoch's profit is split in proportion to
+// `bond_weight_at_epoch / global_weight_at_epoch`.
+
+/// Returns the epoch number for the current block time, using the same `epoch_period`
+/// (in seconds) that governs unbonding batches.
+fn current_epoch(env: &Env, epoch_period: u64) -> u64 {
+    env.block.time.seconds() / epoch_period
+}
+
+/// Brings the global weight accumulator current through `current_epoch` and records a
+/// checkpoint for the epoch if it advanced, so a later claim can recover the global
+/// weight as of any settled epoch in between.
+fn bring_global_current(storage: &mut dyn Storage, current_epoch: u64) -> StdResult<GlobalIndex> {
+    let mut global = GLOBAL.may_load(storage)?.unwrap_or_default();
+
+    if current_epoch > global.last_updated_epoch {
+        let elapsed = current_epoch - global.last_updated_epoch;
+        global.total_weight += global.total_bonded * Uint128::from(elapsed);
+        global.last_updated_epoch = current_epoch;
+        GLOBAL.save(storage, &global)?;
+
+        let mut checkpoints = GLOBAL_CHECKPOINTS.may_load(storage)?.unwrap_or_default();
+        checkpoints.push(global.clone());
+        GLOBAL_CHECKPOINTS.save(storage, &checkpoints)?;
+    }
+
+    Ok(global)
+}
+
+/// Brings a single bonder's weight current through `current_epoch` and records a
+/// checkpoint for the epoch if it advanced. Must be called before any change to
+/// `bond.amount` so the change doesn't retroactively affect already-accrued weight.
+fn bring_bond_current(
+    storage: &mut dyn Storage,
+    address: &Addr,
+    current_epoch: u64,
+) -> StdResult<Bond> {
+    let mut bond = BONDS.may_load(storage, address)?.unwrap_or_default();
+
+    if current_epoch > bond.last_updated_epoch {
+        let elapsed = current_epoch - bond.last_updated_epoch;
+        bond.weight += bond.amount * Uint128::from(elapsed);
+        bond.last_updated_epoch = current_epoch;
+        BONDS.save(storage, address, &bond)?;
+
+        let mut checkpoints = BOND_CHECKPOINTS
+            .may_load(storage, address)?
+            .unwrap_or_default();
+        checkpoints.push(bond.clone());
+        BOND_CHECKPOINTS.save(storage, address, &checkpoints)?;
+    }
+
+    Ok(bond)
+}
+
+/// Recovers a bonder's weight as of `epoch` by interpolating forward from the latest
+/// checkpoint at or before `epoch` (weight accrues linearly since `amount` is constant
+/// between checkpoints).
+fn bond_weight_at_epoch(checkpoints: &[Bond], epoch: u64) -> Uint128 {
+    match checkpoints.iter().rev().find(|c| c.last_updated_epoch <= epoch) {
+        Some(c) => c.weight + c.amount * Uint128::from(epoch - c.last_updated_epoch),
+        None => Uint128::zero(),
+    }
+}
+
+/// Recovers the global weight as of `epoch`, analogous to [`bond_weight_at_epoch`].
+fn global_weight_at_epoch(checkpoints: &[GlobalIndex], epoch: u64) -> Uint128 {
+    match checkpoints.iter().rev().find(|c| c.last_updated_epoch <= epoch) {
+        Some(c) => c.total_weight + c.total_bonded * Uint128::from(epoch - c.last_updated_epoch),
+        None => Uint128::zero(),
+    }
+}
+
+/// Bonds `amount` of vLuna (already transferred to the vault via the `Cw20HookMsg::Bond`
+/// receive hook) into the profit-sharing pool on behalf of `sender`.
+pub fn bond(deps: DepsMut, env: Env, sender: String, amount: Uint128) -> VaultResult {
+    let params = PARAMETERS.load(deps.storage)?;
+    let epoch = current_epoch(&env, params.epoch_period);
+    let sender_addr = deps.api.addr_validate(&sender)?;
+
+    bring_global_current(deps.storage, epoch)?;
+    bring_bond_current(deps.storage, &sender_addr, epoch)?;
+
+    let mut global = GLOBAL.load(deps.storage)?;
+    global.total_bonded += amount;
+    GLOBAL.save(deps.storage, &global)?;
+
+    let mut bond = BONDS.load(deps.storage, &sender_addr)?;
+    bond.amount += amount;
+    BONDS.save(deps.storage, &sender_addr, &bond)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "bond")
+        .add_attribute("address", sender_addr)
+        .add_attribute("amount", amount))
+}
+
+/// Unbonds `amount` of previously-bonded vLuna, returning it to the caller. Weight is
+/// brought current first so the withdrawal doesn't retroactively affect epochs that
+/// have already accrued.
+pub fn withdraw_bond(deps: DepsMut, env: Env, info: MessageInfo, amount: Uint128) -> VaultResult {
+    let params = PARAMETERS.load(deps.storage)?;
+    let epoch = current_epoch(&env, params.epoch_period);
+
+    bring_global_current(deps.storage, epoch)?;
+    let mut bond = bring_bond_current(deps.storage, &info.sender, epoch)?;
+
+    if bond.amount < amount {
+        return Err(LunaVaultError::InsufficientBond {});
+    }
+    bond.amount = bond.amount.checked_sub(amount)?;
+    BONDS.save(deps.storage, &info.sender, &bond)?;
+
+    let mut global = GLOBAL.load(deps.storage)?;
+    global.total_bonded = global.total_bonded.checked_sub(amount)?;
+    GLOBAL.save(deps.storage, &global)?;
+
+    let pool_info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
+    let transfer_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: pool_info.liquidity_token.to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: info.sender.to_string(),
+            amount,
+        })?,
+        funds: vec![],
+    });
+
+    Ok(Response::new()
+        .add_message(transfer_msg)
+        .add_attribute("action", "withdraw_bond")
+        .add_attribute("address", info.sender)
+        .add_attribute("amount", amount))
+}
+
+/// Books `amount` of LUNA profit into the current epoch's distributable bucket. Called
+/// whenever the vault realizes profit that is earmarked for bonders rather than LPs.
+pub fn book_epoch_profit(deps: DepsMut, env: Env, amount: Uint128) -> VaultResult {
+    let params = PARAMETERS.load(deps.storage)?;
+    let epoch = current_epoch(&env, params.epoch_period);
+
+    EPOCH_PROFITS.update(deps.storage, epoch, |existing| -> StdResult<Uint128> {
+        Ok(existing.unwrap_or_default() + amount)
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "book_epoch_profit")
+        .add_attribute("epoch", epoch.to_string())
+        .add_attribute("amount", amount))
+}
+
+/// Claims the caller's pro-rata share of every settled epoch's booked profit since
+/// their last claim, bounded to `BOND_REWARD_PAGE_LIMIT` epochs per call so a bonder
+/// who hasn't claimed in a long time can't blow the gas limit in one transaction. The
+/// current, still-open epoch is never claimable since its profit bucket is still
+/// accumulating.
+pub fn claim(deps: DepsMut, env: Env, info: MessageInfo) -> VaultResult {
+    let params = PARAMETERS.load(deps.storage)?;
+    let current = current_epoch(&env, params.epoch_period);
+
+    bring_global_current(deps.storage, current)?;
+    let mut bond = bring_bond_current(deps.storage, &info.sender, current)?;
+
+    let last_claimable = current.saturating_sub(1);
+    if bond.last_claimed_epoch >= last_claimable {
+        return Ok(Response::new()
+            .add_attribute("action", "claim")
+            .add_attribute("address", info.sender)
+            .add_attribute("amount", Uint128::zero()));
+    }
+
+    let from_epoch = bond.last_claimed_epoch + 1;
+    let to_epoch = std::cmp::min(last_claimable, from_epoch + BOND_REWARD_PAGE_LIMIT - 1);
+
+    let global_checkpoints = GLOBAL_CHECKPOINTS.load(deps.storage)?;
+    let bond_checkpoints = BOND_CHECKPOINTS.load(deps.storage, &info.sender)?;
+
+    let mut reward = Uint128::zero();
+    for epoch in from_epoch..=to_epoch {
+        let epoch_profit = EPOCH_PROFITS
+            .may_load(deps.storage, epoch)?
+            .unwrap_or_default();
+        if epoch_profit.is_zero() {
+            continue;
+        }
+
+        let bond_weight = bond_weight_at_epoch(&bond_checkpoints, epoch);
+        let global_weight = global_weight_at_epoch(&global_checkpoints, epoch);
+        if global_weight.is_zero() {
+            continue;
+        }
+
+        reward += epoch_profit.multiply_ratio(bond_weight, global_weight);
+    }
+
+    bond.last_claimed_epoch = to_epoch;
+    BONDS.save(deps.storage, &info.sender, &bond)?;
+
+    let mut response = Response::new()
+        .add_attribute("action", "claim")
+        .add_attribute("address", info.sender.clone())
+        .add_attribute("claimed_through_epoch", to_epoch.to_string())
+        .add_attribute("amount", reward);
+
+    if !reward.is_zero() {
+        response = response.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(reward.u128(), LUNA_DENOM),
+        });
+    }
+
+    Ok(response)
 }
\ No newline at end of file
@@ -1,18 +1,92 @@
 use core::result::Result::Err;
-use cosmwasm_std::{CosmosMsg, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Uint128, WasmMsg};
+use cosmwasm_std::{CosmosMsg, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Uint128, WasmMsg};
 use terraswap::asset::{Asset, AssetInfo};
+use white_whale::anchor::{anchor_deposit_msg, anchor_withdraw_msg};
 use white_whale::denom::LUNA_DENOM;
 use white_whale::luna_vault::msg::{CallbackMsg, FlashLoanPayload};
+use white_whale::query::anchor::query_aust_exchange_rate;
 use white_whale::tax::into_msg_without_tax;
+use white_whale::treasury::dapp_base::commands::resolve_money_market;
+use white_whale::treasury::dapp_base::common::ANCHOR_MONEY_MARKET_ID;
+use crate::commands::record_fee;
 use crate::contract;
 use crate::contract::VaultResult;
 use crate::error::LunaVaultError;
 use crate::helpers::compute_total_value;
 use crate::pool_info::PoolInfoRaw;
-use crate::state::{DEPOSIT_INFO, FEE, POOL_INFO, PROFIT, STATE};
+use crate::commands::{
+    book_epoch_profit, compute_flash_loan_fee, is_whitelist_entry_expired, reserve_block_loan_budget,
+};
+use crate::state::{
+    CURRENT_BATCH, DEPOSIT_INFO, FEE, MAX_LOAN_DEPTH, PASSIVE_STRATEGY_IDLE_BUFFER, POOL_INFO,
+    PROFIT, STATE, WHITELIST,
+};
 
 const ROUNDING_ERR_COMPENSATION: u32 = 10u32;
 
+/// Maximum nested flash-loan depth when no admin-configured `MAX_LOAN_DEPTH` is set.
+const DEFAULT_MAX_LOAN_DEPTH: u32 = 5;
+
+//----------------------------------------------------------------------------------------
+//  PASSIVE STRATEGY ADAPTER
+//----------------------------------------------------------------------------------------
+//
+// Idle vault LUNA is kept deposited in an external yield venue (Anchor's money market,
+// today) between flash loans, the way a C2C lending adapter keeps idle collateral
+// earning yield elsewhere until it's called on. `compute_total_value` already counts
+// strategy-deposited funds at their redeemable value, so the solvency check in
+// `handle_flashloan` stays
+// correct whether or not funds are currently parked.
+
+/// A pluggable external venue that idle vault liquidity can be parked in. Swapping
+/// venues is a matter of registering a different `AdapterKind::MoneyMarket` entry under
+/// the typed address book and implementing this trait for it, not touching the
+/// flash-loan flow.
+trait PassiveStrategyAdapter {
+    /// Builds the message that deposits `amount` LUNA-equivalent into the venue.
+    fn deposit_msg(&self, deps: Deps, amount: Uint128) -> StdResult<CosmosMsg>;
+    /// Builds the message that withdraws `amount` LUNA-equivalent out of the venue.
+    fn withdraw_msg(&self, deps: Deps, env: &Env, amount: Uint128) -> StdResult<CosmosMsg>;
+}
+
+struct AnchorAdapter {
+    bluna_address: cosmwasm_std::Addr,
+    money_market_address: cosmwasm_std::Addr,
+}
+
+impl PassiveStrategyAdapter for AnchorAdapter {
+    fn deposit_msg(&self, _deps: Deps, amount: Uint128) -> StdResult<CosmosMsg> {
+        anchor_deposit_msg(self.money_market_address.clone(), amount)
+    }
+
+    fn withdraw_msg(&self, deps: Deps, env: &Env, amount: Uint128) -> StdResult<CosmosMsg> {
+        let aust_exchange_rate = query_aust_exchange_rate(
+            env.clone(),
+            deps,
+            self.money_market_address.to_string(),
+        )?;
+
+        anchor_withdraw_msg(
+            self.bluna_address.clone(),
+            self.money_market_address.clone(),
+            amount * aust_exchange_rate.inv().unwrap(),
+        )
+    }
+}
+
+/// Resolves the currently registered passive-strategy adapter through the typed
+/// `AdapterKind::MoneyMarket` address-book entry, instead of the untyped string-keyed
+/// `VAULT_ADDRESS_BOOK` this used to maintain in parallel.
+fn resolve_passive_strategy(deps: Deps) -> StdResult<AnchorAdapter> {
+    let state = STATE.load(deps.storage)?;
+    let money_market_address = resolve_money_market(deps, ANCHOR_MONEY_MARKET_ID)?;
+
+    Ok(AnchorAdapter {
+        bluna_address: state.bluna_address,
+        money_market_address,
+    })
+}
+
 pub fn handle_flashloan(
     mut deps: DepsMut,
     env: Env,
@@ -21,76 +95,111 @@ pub fn handle_flashloan(
 ) -> VaultResult {
     let state = STATE.load(deps.storage)?;
     let deposit_info = DEPOSIT_INFO.load(deps.storage)?;
-    let fees = FEE.load(deps.storage)?;
-    let whitelisted_contracts = state.whitelisted_contracts;
-    let whitelisted: bool;
-    // Check if requested asset is base token of vault
-    deposit_info.assert(&payload.requested_asset.info)?;
-
-    // Check if sender is whitelisted
-    if !whitelisted_contracts.contains(&deps.api.addr_validate(&info.sender.to_string())?) {
-        // Check if non-whitelisted are allowed to borrow
-        if state.allow_non_whitelisted {
-            whitelisted = false;
-        } else {
-            return Err(LunaVaultError::NotWhitelisted {});
+    let sender_addr = deps.api.addr_validate(&info.sender.to_string())?;
+    let whitelist_entry = WHITELIST.may_load(deps.storage, &sender_addr)?;
+    let whitelisted: bool = match &whitelist_entry {
+        Some(entry) if !is_whitelist_entry_expired(entry, &env) => true,
+        _ => {
+            // Check if non-whitelisted (or expired) callers are allowed to borrow
+            if state.allow_non_whitelisted {
+                false
+            } else {
+                return Err(LunaVaultError::NotWhitelisted {});
+            }
         }
+    };
+
+    // Backward-compatible with the old single-asset payload shape: `requested_assets`
+    // is the new way to borrow several denoms atomically, but when it's left empty we
+    // fall back to the legacy `requested_asset` field.
+    let requested_assets: Vec<Asset> = if payload.requested_assets.is_empty() {
+        vec![payload.requested_asset]
     } else {
-        whitelisted = true;
+        payload.requested_assets
+    };
+
+    // Check every requested asset is a base token of the vault.
+    for requested_asset in &requested_assets {
+        deposit_info.assert(&requested_asset.info)?;
     }
 
     // Do we have enough funds?
     let pool_info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
     let (total_value, luna_available, _, _, _) = compute_total_value(&env, deps.as_ref(), &pool_info)?;
-    let requested_asset = payload.requested_asset;
+
+    let mut total_requested = Uint128::zero();
+    for requested_asset in &requested_assets {
+        total_requested += requested_asset.amount;
+    }
+
+    if whitelisted {
+        let mut entry = whitelist_entry.unwrap();
+        if total_requested > entry.max_per_call {
+            return Err(LunaVaultError::WhitelistCapExceeded {});
+        }
+        let cumulative_used = entry.cumulative_used + total_requested;
+        if cumulative_used > entry.cumulative_cap {
+            return Err(LunaVaultError::WhitelistCapExceeded {});
+        }
+        entry.cumulative_used = cumulative_used;
+        WHITELIST.save(deps.storage, &sender_addr, &entry)?;
+    }
 
     // Max tax buffer will be 2 transfers of the borrowed assets
     // Passive Strategy -> Vault -> Caller
-    let tax_buffer = Uint128::from(2u32) * requested_asset.compute_tax(&deps.querier)?
-        + Uint128::from(ROUNDING_ERR_COMPENSATION);
+    // Summed across every requested asset, then checked against total_value together --
+    // legs that each fit individually can still overdraw the vault in aggregate.
+    let mut total_tax_buffer = Uint128::zero();
+    for requested_asset in &requested_assets {
+        let tax_buffer = Uint128::from(2u32) * requested_asset.compute_tax(&deps.querier)?
+            + Uint128::from(ROUNDING_ERR_COMPENSATION);
+
+        total_tax_buffer += tax_buffer;
+    }
 
-    if total_value < requested_asset.amount + tax_buffer {
+    if total_value < total_requested + total_tax_buffer {
         return Err(LunaVaultError::Broke {});
     }
+
     // Init response
     let mut response = Response::new().add_attribute("Action", "Flashloan");
 
-    //TODO
-    // Withdraw funds from Passive Strategy if needed
+    // Withdraw the exact aggregate shortfall from the passive strategy before the loan
+    // transfers, so the withdrawal settles and the vault actually holds the funds it's
+    // about to send out across every borrowed asset.
     // FEE_BUFFER as buffer for fees and taxes
-    /*    if (requested_asset.amount + tax_buffer) > luna_available {
-            // Attempt to remove some money from anchor
-            let to_withdraw = (requested_asset.amount + tax_buffer) - luna_available;
-            let aust_exchange_rate = query_aust_exchange_rate(
-                env.clone(),
-                deps.as_ref(),
-                state.anchor_money_market_address.to_string(),
-            )?;
-
-            let withdraw_msg = anchor_withdraw_msg(
-                state.bluna_address,
-                state.anchor_money_market_address,
-                to_withdraw * aust_exchange_rate.inv().unwrap(),
-            )?;
-
-            // Add msg to response and update withdrawn value
-            response = response
-                .add_message(withdraw_msg)
-                .add_attribute("Anchor withdrawal", to_withdraw.to_string())
-                .add_attribute("ust_aust_rate", aust_exchange_rate.to_string());
-        }*/
+    if (total_requested + total_tax_buffer) > luna_available {
+        let to_withdraw = (total_requested + total_tax_buffer) - luna_available;
+        let adapter = resolve_passive_strategy(deps.as_ref())?;
+        let withdraw_msg = adapter.withdraw_msg(deps.as_ref(), &env, to_withdraw)?;
+
+        response = response
+            .add_message(withdraw_msg)
+            .add_attribute("passive_strategy_withdrawal", to_withdraw.to_string());
+    }
 
-    // If caller not whitelisted, calculate flashloan fee
+    // Enforce the per-block aggregate loaned-out ceiling before handing out funds.
+    reserve_block_loan_budget(deps.storage, &env, total_requested)?;
 
-    let loan_fee: Uint128 = if whitelisted {
-        Uint128::zero()
-    } else {
-        fees.flash_loan_fee.compute(requested_asset.amount)
-    };
+    // If caller not whitelisted, accumulate the utilization-scaled fee per borrowed asset.
+    let mut loan_fee = Uint128::zero();
+    if !whitelisted {
+        for requested_asset in &requested_assets {
+            let (fee, rate, utilization) =
+                compute_flash_loan_fee(deps.storage, requested_asset.amount, total_value)?;
+            loan_fee += fee;
+            response = response
+                .add_attribute("flash_loan_fee_rate", rate.to_string())
+                .add_attribute("utilization", utilization.to_string());
+        }
+    }
 
-    // Construct transfer of funds msg, tax is accounted for by buffer
-    let loan_msg = into_msg_without_tax(requested_asset, info.sender.clone())?;
-    response = response.add_message(loan_msg);
+    // Construct transfer of funds msgs, tax is accounted for by buffer. These must come
+    // after the passive-strategy withdrawal message above so the withdraw settles first.
+    for requested_asset in requested_assets {
+        let loan_msg = into_msg_without_tax(requested_asset, info.sender.clone())?;
+        response = response.add_message(loan_msg);
+    }
 
     // Construct return call with received binary
     let return_call = CosmosMsg::Wasm(WasmMsg::Execute {
@@ -108,14 +217,17 @@ pub fn handle_flashloan(
     encapsulate_payload(deps.as_ref(), env, response, loan_fee)
 }
 
-/// Resets last trade and sets current UST balance of caller
+/// Pushes a pre-trade snapshot and bumps the loan-depth counter, so a borrower's
+/// callback can legitimately re-borrow from the vault (recursive arbitrage) instead of
+/// hard-failing on the old boolean-style latch. Each nesting level gets its own
+/// snapshot and is checked for profitability independently by `after_trade`.
 pub fn before_trade(deps: DepsMut, env: Env) -> StdResult<Vec<(&str, String)>> {
     let mut profit_check = PROFIT.load(deps.storage)?;
 
-    // last_balance call can not be reset until after the loan.
-    if profit_check.last_balance != Uint128::zero() {
+    let max_depth = MAX_LOAN_DEPTH.may_load(deps.storage)?.unwrap_or(DEFAULT_MAX_LOAN_DEPTH);
+    if profit_check.depth >= max_depth {
         return Err(StdError::generic_err(
-            LunaVaultError::Nonzero {}.to_string(),
+            LunaVaultError::MaxLoanDepthExceeded {}.to_string(),
         ));
     }
 
@@ -123,13 +235,12 @@ pub fn before_trade(deps: DepsMut, env: Env) -> StdResult<Vec<(&str, String)>> {
 
     // Index 0 = total_value
     let info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
-    profit_check.last_balance = compute_total_value(&env, deps.as_ref(), &info)?.0;
+    let current_balance = compute_total_value(&env, deps.as_ref(), &info)?.0;
+    profit_check.balance_stack.push(current_balance);
+    profit_check.depth += 1;
     PROFIT.save(deps.storage, &profit_check)?;
 
-    Ok(vec![(
-        "value before trade: ",
-        profit_check.last_balance.to_string(),
-    )])
+    Ok(vec![("value before trade: ", current_balance.to_string())])
 }
 
 /// Checks if balance increased after the trade
@@ -139,33 +250,76 @@ pub fn after_trade(
     msg_info: MessageInfo,
     loan_fee: Uint128,
 ) -> VaultResult {
-    // Deposit funds into anchor if applicable.
-    ///TODO this is where the potential passive income strategy could come into play
-    //let response = try_anchor_deposit(deps.branch(), env.clone())?;
-    let response = Response::default();
+    let mut response = Response::default();
 
     let mut conf = PROFIT.load(deps.storage)?;
 
     let info: PoolInfoRaw = POOL_INFO.load(deps.storage)?;
-    let balance = compute_total_value(&env, deps.as_ref(), &info)?.0;
+    let (balance, luna_available, ..) = compute_total_value(&env, deps.as_ref(), &info)?;
+
+    // Pop this nesting level's own pre-trade snapshot so it's checked against its own
+    // loan_fee regardless of how many loans are still open above it on the stack.
+    let last_balance = conf
+        .balance_stack
+        .pop()
+        .ok_or(LunaVaultError::NoActiveLoan {})?;
 
     // Check if balance increased with expected fee, otherwise cancel everything
-    if balance < conf.last_balance + loan_fee {
+    if balance < last_balance + loan_fee {
         return Err(LunaVaultError::CancelLosingTrade {});
     }
 
-    let profit = balance - conf.last_balance;
+    let profit = balance - last_balance;
 
     conf.last_profit = profit;
-    conf.last_balance = Uint128::zero();
+    conf.depth = conf.depth.saturating_sub(1);
     PROFIT.save(deps.storage, &conf)?;
 
+    // Only once the trade is confirmed profitable do we re-deposit any idle surplus
+    // back into the passive strategy, so a reverted trade never strands funds there.
+    let idle_buffer = PASSIVE_STRATEGY_IDLE_BUFFER.may_load(deps.storage)?.unwrap_or_default();
+    if luna_available > idle_buffer {
+        let surplus = luna_available - idle_buffer;
+        let adapter = resolve_passive_strategy(deps.as_ref())?;
+        response = response
+            .add_message(adapter.deposit_msg(deps.as_ref(), surplus)?)
+            .add_attribute("passive_strategy_deposit", surplus.to_string());
+    }
+
+    let commission_amount = FEE.load(deps.storage)?.commission_fee.compute(profit);
     let commission_response = send_commissions(deps.as_ref(), msg_info, profit)?;
 
+    // The commission left the vault above; whatever remains of `profit` stays in the
+    // vault's LUNA balance, raising `total_value` and therefore every LP share's
+    // redeemable value (see `query_lp_share_value`) instead of accruing to no one.
+    let lp_accrued_profit = profit.checked_sub(commission_amount)?;
+
+    // Book this trade's realized profit into the current epoch's distributable bucket,
+    // so bonders' `claim` actually has something to pay out -- without this the
+    // epoch-weighted bonding feature never records a single profit.
+    let epoch_profit_response = book_epoch_profit(deps.branch(), env.clone(), lp_accrued_profit)?;
+    response = response
+        .add_attributes(epoch_profit_response.attributes)
+        .add_submessages(epoch_profit_response.messages);
+
+    if !loan_fee.is_zero() {
+        let current_batch = CURRENT_BATCH.load(deps.storage)?;
+        record_fee(
+            deps.storage,
+            current_batch.id,
+            env.block.time.seconds(),
+            Decimal::one(),
+            Uint128::zero(),
+            Uint128::zero(),
+            loan_fee,
+        )?;
+    }
+
     Ok(response
         // Send commission of profit to Treasury
         .add_submessages(commission_response.messages)
         .add_attributes(commission_response.attributes)
+        .add_attribute("lp_accrued_profit", lp_accrued_profit.to_string())
         .add_attribute("value after commission: ", balance.to_string()))
 }
 
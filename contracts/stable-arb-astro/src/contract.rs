@@ -1,10 +1,14 @@
 use cosmwasm_std::{
-    entry_point, to_binary, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo,
-    Response, StdResult, WasmMsg,
+    entry_point, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env,
+    MessageInfo, QueryRequest, Response, StdError, StdResult, Uint128, WasmMsg, WasmQuery,
 };
 
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg};
+use semver::Version;
 use terra_cosmwasm::{create_swap_msg, TerraMsgWrapper};
 use terraswap::asset::{Asset, AssetInfo};
+use terraswap::pair::ExecuteMsg as TerraswapPairExecuteMsg;
 
 use terraswap::querier::query_balance;
 
@@ -14,12 +18,16 @@ use white_whale::astroport_helper::create_astroport_msg;
 
 use white_whale::deposit_info::ArbBaseAsset;
 use white_whale::query::astroport::simulate_swap as simulate_astroport_swap;
+use white_whale::query::terraswap::simulate_swap as simulate_terraswap_swap;
 use white_whale::tax::deduct_tax;
 use white_whale::ust_vault::msg::ExecuteMsg as VaultMsg;
 use white_whale::ust_vault::msg::FlashLoanPayload;
 
 use crate::error::StableArbError;
-use crate::msg::{ArbDetails, CallbackMsg, ExecuteMsg, InstantiateMsg, QueryMsg};
+use crate::msg::{
+    ArbDetails, CallbackMsg, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, SimulateArbResponse,
+    Venue,
+};
 
 use crate::querier::query_market_price;
 
@@ -27,6 +35,9 @@ use crate::state::{State, ADMIN, ARB_BASE_ASSET, STATE};
 
 type VaultResult = Result<Response<TerraMsgWrapper>, StableArbError>;
 
+const CONTRACT_NAME: &str = "crates.io:stable-arb-astro";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
@@ -34,6 +45,8 @@ pub fn instantiate(
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> VaultResult {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
     let state = State {
         vault_address: deps.api.addr_canonicalize(&msg.vault_address)?,
         seignorage_address: deps.api.addr_canonicalize(&msg.seignorage_address)?,
@@ -54,14 +67,39 @@ pub fn instantiate(
     Ok(Response::default())
 }
 
+// Handles in-place storage upgrades. Future schema changes to `State`/`ArbBaseAsset`
+// should be transformed here, keyed off `stored.version`, instead of forcing a fresh
+// instantiate; downgrades are rejected since we never wrote a backwards transform.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> VaultResult {
+    let stored = get_contract_version(deps.storage)?;
+
+    if stored.contract != CONTRACT_NAME {
+        return Err(StableArbError::InvalidMigration {});
+    }
+    // Compare numerically, not as strings -- "9.9.9" >= "10.0.0" lexicographically, which
+    // would reject a legitimate upgrade into a double-digit version as a downgrade.
+    let stored_version =
+        Version::parse(&stored.version).map_err(|_| StableArbError::InvalidMigration {})?;
+    let new_version =
+        Version::parse(CONTRACT_VERSION).map_err(|_| StableArbError::InvalidMigration {})?;
+    if stored_version >= new_version {
+        return Err(StableArbError::InvalidMigration {});
+    }
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("previous_version", stored.version)
+        .add_attribute("new_version", CONTRACT_VERSION))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> VaultResult {
     match msg {
-        ExecuteMsg::ExecuteArb { details, above_peg } => {
-            call_flashloan(deps, env, info, details, above_peg)
-        }
-        ExecuteMsg::BelowPegCallback { details } => try_arb_below_peg(deps, env, info, details),
-        ExecuteMsg::AbovePegCallback { details } => try_arb_above_peg(deps, env, info, details),
+        ExecuteMsg::ExecuteArb { details } => call_flashloan(deps, env, info, details),
+        ExecuteMsg::RouteCallback { details } => execute_route(deps, env, info, details),
         ExecuteMsg::SetAdmin { admin } => {
             let admin_addr = deps.api.addr_validate(&admin)?;
             let previous_admin = ADMIN.get(deps.as_ref())?.unwrap();
@@ -84,7 +122,11 @@ fn _handle_callback(deps: DepsMut, env: Env, info: MessageInfo, msg: CallbackMsg
         return Err(StableArbError::NotCallback {});
     }
     match msg {
-        CallbackMsg::AfterSuccessfulTradeCallback {} => after_successful_trade_callback(deps, env),
+        CallbackMsg::AfterSuccessfulTradeCallback {
+            principal,
+            loan_fee,
+            min_profit,
+        } => after_successful_trade_callback(deps, env, principal, loan_fee, min_profit),
         // Possibility to add more callbacks in future.
     }
 }
@@ -92,12 +134,39 @@ fn _handle_callback(deps: DepsMut, env: Env, info: MessageInfo, msg: CallbackMsg
 //  EXECUTE FUNCTION HANDLERS
 //----------------------------------------------------------------------------------------
 
+/// Queries the contract's own balance of `asset_info`, whichever variant it is, so the
+/// solvency checks below don't have to special-case CW20-denominated vaults.
+///
+/// CW20 support in this contract stops at balance-checking and repayment: a
+/// CW20-denominated vault's base asset can be queried here and repaid in
+/// `after_successful_trade_callback` via `Cw20ExecuteMsg::Transfer`, but `execute_route`
+/// and `simulate_arb` still require a native base asset. Every venue this contract routes
+/// through trades native LUNA against a native stable denom -- in particular the Terra
+/// Market module has no CW20 concept at all -- so there is no CW20 equivalent of a route
+/// leg to generalize `build_leg` into. A CW20-denominated vault can hold funds here and be
+/// repaid, but can't actually route an arb through this contract.
+fn query_asset_balance(deps: Deps, asset_info: &AssetInfo, address: &Addr) -> StdResult<Uint128> {
+    match asset_info {
+        AssetInfo::NativeToken { denom } => {
+            query_balance(&deps.querier, address.clone(), denom.clone())
+        }
+        AssetInfo::Token { contract_addr } => {
+            let response: BalanceResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20QueryMsg::Balance {
+                    address: address.to_string(),
+                })?,
+            }))?;
+            Ok(response.balance)
+        }
+    }
+}
+
 fn call_flashloan(
     deps: DepsMut,
     _env: Env,
     _msg_info: MessageInfo,
     details: ArbDetails,
-    above_peg: bool,
 ) -> VaultResult {
     let state = STATE.load(deps.storage)?;
     let deposit_info = ARB_BASE_ASSET.load(deps.storage)?;
@@ -105,17 +174,11 @@ fn call_flashloan(
     // Check if requested asset is same as strategy base asset
     deposit_info.assert(&details.asset.info)?;
 
-    // Construct callback msg
-    let callback_msg;
-    if above_peg {
-        callback_msg = ExecuteMsg::AbovePegCallback {
-            details: details.clone(),
-        }
-    } else {
-        callback_msg = ExecuteMsg::BelowPegCallback {
-            details: details.clone(),
-        }
-    }
+    // Construct callback msg. The route itself (which venues, in which order) lives on
+    // `details.route`, so there's no separate above/below-peg callback to pick between.
+    let callback_msg = ExecuteMsg::RouteCallback {
+        details: details.clone(),
+    };
 
     // Construct payload
     let payload = FlashLoanPayload {
@@ -133,85 +196,72 @@ fn call_flashloan(
     )
 }
 
-// Attempt to perform an arbitrage operation with the assumption that
-// the currency to be arb'd is below peg. Needed funds should be provided
-// by the earlier stablecoin vault flashloan call.
-
-pub fn try_arb_below_peg(
-    deps: DepsMut,
-    env: Env,
-    msg_info: MessageInfo,
-    details: ArbDetails,
-) -> VaultResult {
-    let state = STATE.load(deps.storage)?;
-    let deposit_info = ARB_BASE_ASSET.load(deps.storage)?;
-
-    // Ensure the caller is the vault
-    if deps.api.addr_canonicalize(&msg_info.sender.to_string())? != state.vault_address {
-        return Err(StableArbError::Unauthorized {});
+// Flips between the stable denom and LUNA. Every venue this contract knows how to
+// route through (Terra Market, Astroport, terraswap) trades one of these two native
+// assets for the other, so a leg's ask denom is always implied by what it's offering.
+fn other_native_denom(current: &str, stable_denom: &str) -> String {
+    if current == stable_denom {
+        LUNA_DENOM.to_string()
+    } else {
+        stable_denom.to_string()
     }
+}
 
-    // Set vars
-    let denom = deposit_info.get_denom()?;
-    let lent_coin = deduct_tax(
-        deps.as_ref(),
-        Coin::new(details.asset.amount.u128(), denom.clone()),
-    )?;
-    let ask_denom = LUNA_DENOM.to_string();
-    let response: Response<TerraMsgWrapper> = Response::new();
-
-    // Check if we have enough funds
-    let balance = query_balance(&deps.querier, env.contract.address.clone(), denom)?;
-    if balance < details.asset.amount {
-        return Err(StableArbError::Broke {});
+// Builds the `CosmosMsg` for a single route leg and simulates it so the next leg can
+// be built with the expected output amount, without assuming any fixed venue pairing.
+fn build_leg(
+    deps: Deps,
+    venue: &Venue,
+    pool: &Addr,
+    offer: Coin,
+    stable_denom: &str,
+    belief_price: Option<Decimal>,
+    slippage: Decimal,
+) -> StdResult<(CosmosMsg<TerraMsgWrapper>, Uint128)> {
+    match venue {
+        Venue::TerraMarket => {
+            let ask_denom = other_native_denom(&offer.denom, stable_denom);
+            let expected = query_market_price(deps, offer.clone(), ask_denom.clone())?;
+            Ok((create_swap_msg(offer, ask_denom), expected))
+        }
+        Venue::Astroport => {
+            let expected = simulate_astroport_swap(deps, pool.clone(), offer.clone())?;
+            let msg = CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: pool.to_string(),
+                funds: vec![offer.clone()],
+                msg: to_binary(&create_astroport_msg(offer, belief_price, Some(slippage)))?,
+            });
+            Ok((msg, expected))
+        }
+        Venue::Terraswap => {
+            let expected = simulate_terraswap_swap(deps, pool.clone(), offer.clone())?;
+            let offer_asset = Asset {
+                info: AssetInfo::NativeToken {
+                    denom: offer.denom.clone(),
+                },
+                amount: offer.amount,
+            };
+            let msg = CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: pool.to_string(),
+                funds: vec![offer],
+                msg: to_binary(&TerraswapPairExecuteMsg::Swap {
+                    offer_asset,
+                    belief_price,
+                    max_spread: Some(slippage),
+                    to: None,
+                })?,
+            });
+            Ok((msg, expected))
+        }
     }
-
-    // Simulate first tx with Terra Market Module
-    // lent_coin already takes transfer tax into account.
-    let expected_luna_received =
-        query_market_price(deps.as_ref(), lent_coin.clone(), ask_denom.clone())?;
-
-    // Construct offer for Astroport
-    let offer_coin = Coin {
-        denom: ask_denom.clone(),
-        amount: expected_luna_received,
-    };
-
-    // Market swap msg, swap STABLE -> LUNA
-    let swap_msg = create_swap_msg(lent_coin.clone(), ask_denom);
-
-    // Astroport msg, swap LUNA -> STABLE
-    let astroport_msg = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: deps.api.addr_humanize(&state.pool_address)?.to_string(),
-        funds: vec![offer_coin.clone()],
-        msg: to_binary(&create_astroport_msg(
-            offer_coin,
-            details.belief_price,
-            Some(details.slippage),
-        ))?,
-    });
-
-    let logs = vec![
-        ("action", String::from("arb below peg")),
-        ("offer_amount", lent_coin.amount.to_string()),
-        ("expected_luna", expected_luna_received.to_string()),
-    ];
-
-    // Create callback, this will send the funds back to the vault.
-    let callback_msg =
-        CallbackMsg::AfterSuccessfulTradeCallback {}.to_cosmos_msg(&env.contract.address)?;
-
-    Ok(response
-        .add_attributes(logs)
-        .add_message(swap_msg)
-        .add_message(astroport_msg)
-        .add_message(callback_msg))
 }
 
-// Attempt to perform an arbitrage operation with the assumption that
-// the currency to be arb'd is above peg. Needed funds should be provided
-// by the earlier stablecoin vault flashloan call.
-pub fn try_arb_above_peg(
+// Walks `details.route.legs` in order, building each leg's swap message from the
+// previous leg's simulated output. This replaces the old pair of near-duplicate
+// below/above-peg functions: the route itself encodes direction and venue choice, so
+// governance can register additional pools (a second stable pool, a terraswap pair,
+// etc.) without the contract needing a new code path per combination.
+pub fn execute_route(
     deps: DepsMut,
     env: Env,
     msg_info: MessageInfo,
@@ -225,61 +275,62 @@ pub fn try_arb_above_peg(
         return Err(StableArbError::Unauthorized {});
     }
 
-    // Set vars
-    let denom = deposit_info.get_denom()?;
-    let lent_coin = deduct_tax(
-        deps.as_ref(),
-        Coin::new(details.asset.amount.u128(), denom.clone()),
-    )?;
-    let ask_denom = LUNA_DENOM.to_string();
-    let response: Response<TerraMsgWrapper> = Response::new();
+    if details.route.legs.is_empty() {
+        return Err(StableArbError::EmptyRoute {});
+    }
 
-    // Check if we have enough funds
-    let balance = query_balance(&deps.querier, env.contract.address.clone(), denom)?;
+    // Check if we have enough funds, whether the base asset is a native denom or a CW20.
+    let balance =
+        query_asset_balance(deps.as_ref(), &deposit_info.asset_info, &env.contract.address)?;
     if balance < details.asset.amount {
         return Err(StableArbError::Broke {});
     }
-    // Simulate first tx with Astroport
-    let expected_luna_received = simulate_astroport_swap(
-        deps.as_ref(),
-        deps.api.addr_humanize(&state.pool_address)?,
-        lent_coin.clone(),
-    )?;
 
-    // Construct offer for Market Swap
-    let offer_coin = Coin {
-        denom: ask_denom,
-        amount: expected_luna_received,
+    // Every registered venue trades the stable denom against LUNA, which is inherently
+    // native -- there's no CW20 equivalent of a Terra Market swap, so a CW20-denominated
+    // vault can't route through this executor.
+    let denom = match &deposit_info.asset_info {
+        AssetInfo::NativeToken { denom } => denom.clone(),
+        AssetInfo::Token { .. } => return Err(StableArbError::MarketSwapRequiresNativeAsset {}),
     };
-
-    // Astroport msg, swap STABLE -> LUNA
-    let astroport_msg: CosmosMsg<TerraMsgWrapper> = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: deps.api.addr_humanize(&state.pool_address)?.to_string(),
-        funds: vec![lent_coin.clone()],
-        msg: to_binary(&create_astroport_msg(
-            lent_coin.clone(),
+    let lent_coin = deduct_tax(deps.as_ref(), Coin::new(details.asset.amount.u128(), denom.clone()))?;
+
+    let mut response: Response<TerraMsgWrapper> = Response::new();
+    let mut current = lent_coin.clone();
+    for (venue, pool) in &details.route.legs {
+        let (swap_msg, next_amount) = build_leg(
+            deps.as_ref(),
+            venue,
+            pool,
+            current.clone(),
+            &denom,
             details.belief_price,
-            Some(details.slippage),
-        ))?,
-    });
-
-    // Market swap msg, swap LUNA -> STABLE
-    let swap_msg = create_swap_msg(offer_coin, lent_coin.denom);
+            details.slippage,
+        )?;
+        response = response.add_message(swap_msg);
+        current = Coin {
+            denom: other_native_denom(&current.denom, &denom),
+            amount: next_amount,
+        };
+    }
 
     let logs = vec![
-        ("action", String::from("arb above peg")),
+        ("action", String::from("execute arb route")),
         ("offer_amount", lent_coin.amount.to_string()),
-        ("expected_luna", expected_luna_received.to_string()),
+        ("expected_return", current.amount.to_string()),
     ];
 
-    // Create callback, this will send the funds back to the vault.
-    let callback_msg =
-        CallbackMsg::AfterSuccessfulTradeCallback {}.to_cosmos_msg(&env.contract.address)?;
+    // Create callback, this will send the funds back to the vault, checking that the
+    // round trip at least repaid principal + fee plus the caller's requested profit.
+    let callback_msg = CallbackMsg::AfterSuccessfulTradeCallback {
+        principal: details.asset.amount,
+        loan_fee: details.loan_fee,
+        min_profit: details.min_profit,
+    }
+    .to_cosmos_msg(&env.contract.address)?;
 
     Ok(response
         .add_attributes(logs)
-        .add_message(astroport_msg)
-        .add_message(swap_msg)
         .add_message(callback_msg))
 }
 
@@ -287,25 +338,54 @@ pub fn try_arb_above_peg(
 //  CALLBACK FUNCTION HANDLERS
 //----------------------------------------------------------------------------------------
 
-// After the arb this function returns the funds to the vault.
-fn after_successful_trade_callback(deps: DepsMut, env: Env) -> VaultResult {
+// After the arb this function returns the funds to the vault, repaying a native
+// denom via a bank send or a CW20 via a token transfer, depending on the base asset.
+// Aborts the whole atomic transaction if the round trip didn't at least repay the
+// loan principal and fee plus the caller's requested minimum profit.
+fn after_successful_trade_callback(
+    deps: DepsMut,
+    env: Env,
+    principal: Uint128,
+    loan_fee: Uint128,
+    min_profit: Uint128,
+) -> VaultResult {
     let state = STATE.load(deps.storage)?;
-    let stable_denom = ARB_BASE_ASSET.load(deps.storage)?.get_denom()?;
-    let stables_in_contract =
-        query_balance(&deps.querier, env.contract.address, stable_denom.clone())?;
-
-    // Send asset back to vault
-    let repay_asset = Asset {
-        info: AssetInfo::NativeToken {
-            denom: stable_denom,
-        },
-        amount: stables_in_contract,
-    };
+    let deposit_info = ARB_BASE_ASSET.load(deps.storage)?;
+    let vault_address = deps.api.addr_humanize(&state.vault_address)?;
+    let balance =
+        query_asset_balance(deps.as_ref(), &deposit_info.asset_info, &env.contract.address)?;
+
+    let required = principal
+        .checked_add(loan_fee)
+        .map_err(StdError::overflow)?
+        .checked_add(min_profit)
+        .map_err(StdError::overflow)?;
+    if balance < required {
+        return Err(StableArbError::UnprofitableTrade {});
+    }
 
-    Ok(Response::new().add_message(CosmosMsg::Bank(BankMsg::Send {
-        to_address: deps.api.addr_humanize(&state.vault_address)?.to_string(),
-        amount: vec![repay_asset.deduct_tax(&deps.querier)?],
-    })))
+    match deposit_info.asset_info {
+        AssetInfo::NativeToken { denom } => {
+            let repay_asset = Asset {
+                info: AssetInfo::NativeToken { denom },
+                amount: balance,
+            };
+            Ok(Response::new().add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: vault_address.to_string(),
+                amount: vec![repay_asset.deduct_tax(&deps.querier)?],
+            })))
+        }
+        AssetInfo::Token { contract_addr } => {
+            Ok(Response::new().add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: vault_address.to_string(),
+                    amount: balance,
+                })?,
+                funds: vec![],
+            })))
+        }
+    }
 }
 
 //----------------------------------------------------------------------------------------
@@ -336,10 +416,66 @@ pub fn set_vault_addr(deps: DepsMut, msg_info: MessageInfo, vault_address: Strin
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&try_query_config(deps)?),
+        QueryMsg::SimulateArb { details } => to_binary(&simulate_arb(deps, details)?),
     }
 }
 
 pub fn try_query_config(deps: Deps) -> StdResult<ArbBaseAsset> {
     let info: ArbBaseAsset = ARB_BASE_ASSET.load(deps.storage)?;
     Ok(info)
+}
+
+/// Runs the same pricing logic `execute_route` uses, without broadcasting anything, so a
+/// keeper can decide whether an arb is worth its gas. Walks `details.route.legs` exactly
+/// like the execute path does -- it no longer hardcodes an Astroport/Terra-Market pairing,
+/// so this stays accurate for whatever venues the route actually names.
+pub fn simulate_arb(deps: Deps, details: ArbDetails) -> StdResult<SimulateArbResponse> {
+    let deposit_info = ARB_BASE_ASSET.load(deps.storage)?;
+
+    if details.route.legs.is_empty() {
+        return Err(StdError::generic_err("SimulateArb requires a non-empty route"));
+    }
+
+    // Every registered venue trades the stable denom against LUNA, which is inherently
+    // native -- mirrors the same restriction `execute_route` enforces for CW20-denominated
+    // vaults.
+    let denom = match &deposit_info.asset_info {
+        AssetInfo::NativeToken { denom } => denom.clone(),
+        AssetInfo::Token { .. } => {
+            return Err(StdError::generic_err(
+                "SimulateArb requires a native base asset: the Terra Market leg has no CW20 equivalent",
+            ))
+        }
+    };
+    let lent_coin = deduct_tax(deps, Coin::new(details.asset.amount.u128(), denom.clone()))?;
+
+    let mut current = lent_coin.clone();
+    let mut luna_amount = Uint128::zero();
+    for (i, (venue, pool)) in details.route.legs.iter().enumerate() {
+        let (_, next_amount) = build_leg(
+            deps,
+            venue,
+            pool,
+            current.clone(),
+            &denom,
+            details.belief_price,
+            details.slippage,
+        )?;
+        if i == 0 {
+            luna_amount = next_amount;
+        }
+        current = Coin {
+            denom: other_native_denom(&current.denom, &denom),
+            amount: next_amount,
+        };
+    }
+
+    let expected_return = current.amount;
+    let expected_profit = expected_return.saturating_sub(lent_coin.amount);
+
+    Ok(SimulateArbResponse {
+        expected_return,
+        expected_profit,
+        luna_amount,
+    })
 }
\ No newline at end of file
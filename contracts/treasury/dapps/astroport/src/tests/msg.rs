@@ -1,9 +1,14 @@
-use cosmwasm_std::{to_binary, Addr, StdError, Uint128, SubMsg, WasmMsg, CosmosMsg};
+use cosmwasm_std::{to_binary, Addr, Coin, StdError, Uint128, SubMsg, WasmMsg, CosmosMsg};
 use cosmwasm_std::testing::{mock_env, mock_info};
 
+use white_whale::treasury::dapp_base::commands::{assert_trader_or_permit, query_adapter_balance};
+use white_whale::treasury::dapp_base::permit::{Permit, PermitOperation, PermitParams};
+
+use white_whale::query::balance::AssetQueryKind;
+use white_whale::treasury::dapp_base::common::{AdapterKind, ContractStatus, EmergencyAction};
 use white_whale::treasury::dapp_base::error::BaseDAppError;
 use white_whale::treasury::dapp_base::msg::BaseExecuteMsg;
-use white_whale::treasury::dapp_base::state::{ADMIN, BaseState, load_contract_addr, STATE};
+use white_whale::treasury::dapp_base::state::{ADMIN, BaseState, CONTRACT_STATUS, load_contract_addr, STATE};
 
 use crate::contract::execute;
 use crate::msg::ExecuteMsg;
@@ -206,7 +211,7 @@ pub fn test_successfully_update_address_book_add_address_msg() {
     mock_instantiate(deps.as_mut());
     let env = mock_env();
     let msg = ExecuteMsg::Base(BaseExecuteMsg::UpdateAddressBook {
-        to_add: vec![("asset".to_string(), "address".to_string())],
+        to_add: vec![("asset".to_string(), "address".to_string(), AdapterKind::Token, AssetQueryKind::Cw20)],
         to_remove: vec![],
     });
 
@@ -225,7 +230,7 @@ pub fn test_successfully_update_address_book_remove_address_msg() {
 
     // add address
     let msg = ExecuteMsg::Base(BaseExecuteMsg::UpdateAddressBook {
-        to_add: vec![("asset".to_string(), "address".to_string())],
+        to_add: vec![("asset".to_string(), "address".to_string(), AdapterKind::Token, AssetQueryKind::Cw20)],
         to_remove: vec![],
     });
 
@@ -262,7 +267,7 @@ pub fn test_successfully_update_address_book_add_and_removeaddress_msg() {
 
     //add address
     let msg = ExecuteMsg::Base(BaseExecuteMsg::UpdateAddressBook {
-        to_add: vec![("asset".to_string(), "address".to_string())],
+        to_add: vec![("asset".to_string(), "address".to_string(), AdapterKind::Token, AssetQueryKind::Cw20)],
         to_remove: vec![],
     });
 
@@ -282,7 +287,12 @@ pub fn test_successfully_update_address_book_add_and_removeaddress_msg() {
 
     //add and remove addresses
     let msg = ExecuteMsg::Base(BaseExecuteMsg::UpdateAddressBook {
-        to_add: vec![("another_asset".to_string(), "another_address".to_string())],
+        to_add: vec![(
+            "another_asset".to_string(),
+            "another_address".to_string(),
+            AdapterKind::Token,
+            AssetQueryKind::Cw20,
+        )],
         to_remove: vec!["asset".to_string()],
     });
     let info = mock_info(TEST_CREATOR, &[]);
@@ -444,4 +454,408 @@ pub fn test_successful_astro_swap(){
     let res = execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
 
     assert_eq!(res.messages.len(), 1);
+}
+
+/**
+ * BaseExecuteMsg::SetContractStatus
+ */
+#[test]
+pub fn test_unsuccessfully_set_contract_status_msg() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    let env = mock_env();
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::SetContractStatus {
+        status: ContractStatus::Paused,
+    });
+
+    let info = mock_info("unauthorized", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    match res {
+        Err(AstroportError::BaseDAppError(BaseDAppError::Admin(_))) => (),
+        Ok(_) => panic!("Should return unauthorized Error, Admin(NotAdmin)"),
+        _ => panic!("Should return unauthorized Error, Admin(NotAdmin)"),
+    }
+}
+
+#[test]
+pub fn test_successfully_set_contract_status_msg() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    let env = mock_env();
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::SetContractStatus {
+        status: ContractStatus::Paused,
+    });
+
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let status = CONTRACT_STATUS.load(deps.as_ref().storage).unwrap();
+    assert_eq!(status, ContractStatus::Paused);
+}
+
+#[test]
+pub fn test_swap_rejected_while_paused() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    mock_add_to_address_book(deps.as_mut(), ("asset".to_string(), WHALE_TOKEN.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("pool".to_string(), WHALE_UST_PAIR.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("whale_ust".to_string(), WHALE_UST_LP_TOKEN.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("whale_ust_pair".to_string(), WHALE_UST_PAIR.to_string()));
+
+    let env = mock_env();
+
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::SetContractStatus {
+        status: ContractStatus::Paused,
+    });
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::SwapAsset {
+        pool_id: "pool".to_string(),
+        offer_id: "asset".to_string(),
+        amount: Uint128::new(1),
+        max_spread: None,
+        belief_price: None,
+    };
+    let info = mock_info(TRADER_CONTRACT, &[]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    match res {
+        Err(AstroportError::BaseDAppError(BaseDAppError::ContractPaused {})) => (),
+        Ok(_) => panic!("Should return BaseDAppError::ContractPaused"),
+        _ => panic!("Should return BaseDAppError::ContractPaused"),
+    }
+}
+
+#[test]
+pub fn test_swap_resumes_after_unpausing() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    mock_add_to_address_book(deps.as_mut(), ("asset".to_string(), WHALE_TOKEN.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("pool".to_string(), WHALE_UST_PAIR.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("whale_ust".to_string(), WHALE_UST_LP_TOKEN.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("whale_ust_pair".to_string(), WHALE_UST_PAIR.to_string()));
+
+    let env = mock_env();
+
+    // pause
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::SetContractStatus {
+        status: ContractStatus::Paused,
+    });
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // resume
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::SetContractStatus {
+        status: ContractStatus::Operational,
+    });
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::SwapAsset {
+        pool_id: "pool".to_string(),
+        offer_id: "asset".to_string(),
+        amount: Uint128::new(1),
+        max_spread: None,
+        belief_price: None,
+    };
+    let info = mock_info(TRADER_CONTRACT, &[]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+}
+
+/**
+ * BaseExecuteMsg::EmergencyUpdate
+ */
+#[test]
+pub fn test_unsuccessfully_emergency_update_msg() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    let env = mock_env();
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::EmergencyUpdate {
+        action: EmergencyAction::DisableSwap("pool".to_string()),
+    });
+
+    let info = mock_info("unauthorized", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    match res {
+        Err(AstroportError::BaseDAppError(BaseDAppError::NotEmergencyOwner {})) => (),
+        Ok(_) => panic!("Should return BaseDAppError::NotEmergencyOwner"),
+        _ => panic!("Should return BaseDAppError::NotEmergencyOwner"),
+    }
+}
+
+#[test]
+pub fn test_frozen_pool_rejects_swap_while_others_trade() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    mock_add_to_address_book(deps.as_mut(), ("asset".to_string(), WHALE_TOKEN.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("pool".to_string(), WHALE_UST_PAIR.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("whale_ust".to_string(), WHALE_UST_LP_TOKEN.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("whale_ust_pair".to_string(), WHALE_UST_PAIR.to_string()));
+    mock_add_to_address_book(deps.as_mut(), ("other_pool".to_string(), WHALE_UST_PAIR.to_string()));
+
+    let env = mock_env();
+
+    // freeze "pool" only
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::EmergencyUpdate {
+        action: EmergencyAction::DisableSwap("pool".to_string()),
+    });
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // frozen pool rejects the swap
+    let msg = ExecuteMsg::SwapAsset {
+        pool_id: "pool".to_string(),
+        offer_id: "asset".to_string(),
+        amount: Uint128::new(1),
+        max_spread: None,
+        belief_price: None,
+    };
+    let info = mock_info(TRADER_CONTRACT, &[]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg);
+    match res {
+        Err(AstroportError::BaseDAppError(BaseDAppError::AssetFrozen {})) => (),
+        Ok(_) => panic!("Should return BaseDAppError::AssetFrozen"),
+        _ => panic!("Should return BaseDAppError::AssetFrozen"),
+    }
+
+    // other_pool is unaffected and still trades
+    let msg = ExecuteMsg::SwapAsset {
+        pool_id: "other_pool".to_string(),
+        offer_id: "asset".to_string(),
+        amount: Uint128::new(1),
+        max_spread: None,
+        belief_price: None,
+    };
+    let info = mock_info(TRADER_CONTRACT, &[]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(res.messages.len(), 1);
+}
+
+/**
+ * AssetQueryKind / query_adapter_balance
+ */
+#[test]
+pub fn test_query_adapter_balance_native() {
+    let env = mock_env();
+    let mut deps = mock_dependencies(&[Coin::new(1000u128, "uusd")]);
+    mock_instantiate(deps.as_mut());
+
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::UpdateAddressBook {
+        to_add: vec![(
+            "ust".to_string(),
+            "uusd".to_string(),
+            AdapterKind::Token,
+            AssetQueryKind::Native,
+        )],
+        to_remove: vec![],
+    });
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let balance = query_adapter_balance(deps.as_ref(), "ust", &env.contract.address).unwrap();
+    assert_eq!(balance, Uint128::new(1000));
+}
+
+#[test]
+pub fn test_query_adapter_balance_cw20() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    let env = mock_env();
+
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::UpdateAddressBook {
+        to_add: vec![(
+            "asset".to_string(),
+            WHALE_TOKEN.to_string(),
+            AdapterKind::Token,
+            AssetQueryKind::Cw20,
+        )],
+        to_remove: vec![],
+    });
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    query_adapter_balance(deps.as_ref(), "asset", &Addr::unchecked(TRADER_CONTRACT)).unwrap();
+}
+
+#[test]
+pub fn test_query_adapter_balance_smart_token() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    let env = mock_env();
+
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::UpdateAddressBook {
+        to_add: vec![(
+            "asset".to_string(),
+            WHALE_TOKEN.to_string(),
+            AdapterKind::Token,
+            AssetQueryKind::SmartToken,
+        )],
+        to_remove: vec![],
+    });
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // SmartTokenQueryMsg::Balance serializes identically to Cw20QueryMsg::Balance, so the
+    // same mocked response answers it.
+    query_adapter_balance(deps.as_ref(), "asset", &Addr::unchecked(TRADER_CONTRACT)).unwrap();
+}
+
+/**
+ * Permit-based authorization
+ */
+fn sign_permit_with_key(
+    id: &str,
+    allowed_operations: Vec<PermitOperation>,
+    contract_address: &str,
+    secret_key_bytes: [u8; 32],
+) -> Permit {
+    let secp = secp256k1::Secp256k1::signing_only();
+    let secret_key = secp256k1::SecretKey::from_slice(&secret_key_bytes).unwrap();
+    let public_key = secp256k1::PublicKey::from_secret_key(&secp, &secret_key);
+
+    let params = PermitParams {
+        allowed_operations,
+        contract_address: contract_address.to_string(),
+    };
+    let sign_bytes = to_binary(&params).unwrap();
+    let hash = <sha2::Sha256 as sha2::Digest>::digest(sign_bytes.as_slice());
+    let message = secp256k1::Message::from_slice(&hash).unwrap();
+    let signature = secp.sign_ecdsa(&message, &secret_key);
+
+    Permit {
+        id: id.to_string(),
+        params,
+        pubkey: Binary::from(public_key.serialize().to_vec()),
+        signature: Binary::from(signature.serialize_compact().to_vec()),
+    }
+}
+
+fn sign_permit(id: &str, allowed_operations: Vec<PermitOperation>, contract_address: &str) -> Permit {
+    sign_permit_with_key(id, allowed_operations, contract_address, [7u8; 32])
+}
+
+fn register_delegate_pubkey(deps: cosmwasm_std::DepsMut, env: &cosmwasm_std::Env, pubkey: Binary) {
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::SetDelegatePubkey { pubkey });
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps, env.clone(), info, msg).unwrap();
+}
+
+#[test]
+pub fn test_valid_permit_authorizes_swap() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    let env = mock_env();
+
+    let permit = sign_permit(
+        "permit-1",
+        vec![PermitOperation::Swap],
+        env.contract.address.as_str(),
+    );
+    register_delegate_pubkey(deps.as_mut(), &env, permit.pubkey.clone());
+
+    assert_trader_or_permit(
+        deps.as_ref(),
+        &env.contract.address,
+        &Addr::unchecked("delegated_key"),
+        Some(&permit),
+        PermitOperation::Swap,
+    )
+    .unwrap();
+}
+
+#[test]
+pub fn test_permit_with_untrusted_pubkey_rejected() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    let env = mock_env();
+
+    // An attacker signs with their own freshly generated key; it's never registered as
+    // the DELEGATE_PUBKEY, so the signature being internally consistent isn't enough.
+    let permit = sign_permit_with_key(
+        "permit-1",
+        vec![PermitOperation::Swap],
+        env.contract.address.as_str(),
+        [42u8; 32],
+    );
+
+    let res = assert_trader_or_permit(
+        deps.as_ref(),
+        &env.contract.address,
+        &Addr::unchecked("attacker"),
+        Some(&permit),
+        PermitOperation::Swap,
+    );
+
+    match res {
+        Err(BaseDAppError::Unauthorized {}) => (),
+        Ok(_) => panic!("Should return BaseDAppError::Unauthorized"),
+        _ => panic!("Should return BaseDAppError::Unauthorized"),
+    }
+}
+
+#[test]
+pub fn test_revoked_permit_rejected() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    let env = mock_env();
+
+    let permit = sign_permit(
+        "permit-1",
+        vec![PermitOperation::Swap],
+        env.contract.address.as_str(),
+    );
+    register_delegate_pubkey(deps.as_mut(), &env, permit.pubkey.clone());
+
+    let msg = ExecuteMsg::Base(BaseExecuteMsg::RevokePermit {
+        id: "permit-1".to_string(),
+    });
+    let info = mock_info(TEST_CREATOR, &[]);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let res = assert_trader_or_permit(
+        deps.as_ref(),
+        &env.contract.address,
+        &Addr::unchecked("delegated_key"),
+        Some(&permit),
+        PermitOperation::Swap,
+    );
+
+    match res {
+        Err(BaseDAppError::Unauthorized {}) => (),
+        Ok(_) => panic!("Should return BaseDAppError::Unauthorized"),
+        _ => panic!("Should return BaseDAppError::Unauthorized"),
+    }
+}
+
+#[test]
+pub fn test_permit_scoped_to_wrong_operation_rejected() {
+    let mut deps = mock_dependencies(&[]);
+    mock_instantiate(deps.as_mut());
+    let env = mock_env();
+
+    let permit = sign_permit(
+        "permit-1",
+        vec![PermitOperation::ProvideLiquidity],
+        env.contract.address.as_str(),
+    );
+    register_delegate_pubkey(deps.as_mut(), &env, permit.pubkey.clone());
+
+    let res = assert_trader_or_permit(
+        deps.as_ref(),
+        &env.contract.address,
+        &Addr::unchecked("delegated_key"),
+        Some(&permit),
+        PermitOperation::Swap,
+    );
+
+    match res {
+        Err(BaseDAppError::Unauthorized {}) => (),
+        Ok(_) => panic!("Should return BaseDAppError::Unauthorized"),
+        _ => panic!("Should return BaseDAppError::Unauthorized"),
+    }
 }
\ No newline at end of file
@@ -2,6 +2,7 @@
 #![allow(unused_variables)]
 
 use cosmwasm_std::{Binary, Deps, DepsMut, entry_point, Env, MessageInfo, Response, StdResult};
+use cw2::set_contract_version;
 
 use white_whale::treasury::dapp_base::commands as dapp_base_commands;
 use white_whale::treasury::dapp_base::common::BaseDAppResult;
@@ -10,11 +11,14 @@ use white_whale::treasury::dapp_base::queries as dapp_base_queries;
 use white_whale::treasury::dapp_base::state::{ADMIN, BaseState, STATE};
 
 use crate::commands;
-use crate::msg::{ExecuteMsg, QueryMsg};
+use crate::msg::{ExecuteMsg, MigrateMsg, QueryMsg};
+
+const CONTRACT_NAME: &str = "crates.io:dapp-template";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
-    deps: DepsMut,
+    mut deps: DepsMut,
     _env: Env,
     info: MessageInfo,
     msg: BaseInstantiateMsg,
@@ -26,17 +30,34 @@ pub fn instantiate(
     };
 
     STATE.save(deps.storage, &state)?;
-    ADMIN.set(deps, Some(info.sender))?;
+    ADMIN.set(deps.branch(), Some(info.sender))?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
     Ok(Response::default())
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> BaseDAppResult {
+    // No state-transform steps are needed yet; future upgrades register theirs here,
+    // keyed by the version they migrate away from.
+    dapp_base_commands::handle_base_migrate(deps, CONTRACT_NAME, CONTRACT_VERSION, vec![])
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> BaseDAppResult {
     match msg {
         ExecuteMsg::Base(message) => dapp_base_commands::handle_base_message(deps, info, message),
-        // handle dapp-specific messages here
-        // ExecuteMsg::Custom{} => commands::custom_command(),
+        // handle dapp-specific messages here. Trading/liquidity variants must call
+        // `dapp_base_commands::assert_operational(deps.as_ref())?` first so they're
+        // blocked while the contract is Paused/Migrating, and the relevant
+        // `dapp_base_commands::assert_*_not_frozen(deps.as_ref(), id)?` so an
+        // emergency-owner freeze on that specific pool/asset is honored; base messages
+        // above are intentionally exempt so a paused contract can still be recovered.
+        // ExecuteMsg::Custom{} => {
+        //     dapp_base_commands::assert_operational(deps.as_ref())?;
+        //     dapp_base_commands::assert_swap_not_frozen(deps.as_ref(), &pool_id)?;
+        //     commands::custom_command()
+        // }
     }
 }
 
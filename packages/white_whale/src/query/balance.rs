@@ -0,0 +1,67 @@
+use cosmwasm_std::{
+    to_binary, Addr, BankQuery, BalanceResponse as BankBalanceResponse, Deps, QueryRequest,
+    StdResult, Uint128, WasmQuery,
+};
+use cw20::{BalanceResponse as Cw20BalanceResponse, Cw20QueryMsg};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A custom-module balance query, for chains that expose token balances through their own
+/// module instead of a CW20 `Balance` query (a "smart token"). Shaped like a minimal
+/// `Cw20QueryMsg::Balance` so a chain's query plumbing can be swapped in without touching
+/// callers.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartTokenQueryMsg {
+    Balance { address: String },
+}
+
+/// How to resolve an address-book entry's balance for a given owner. Selected per entry
+/// via `AdapterEntry::asset_kind`, so `WithdrawLiquidity` and swap handlers don't have to
+/// hardcode the CW20 `Balance` query shape for every asset they touch.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum AssetQueryKind {
+    /// A native bank-module denom, queried via `BankQuery::Balance`.
+    Native,
+    /// A balance exposed through a chain's custom module query wrapper rather than CW20.
+    SmartToken,
+    /// A standard CW20 token, queried via `Cw20QueryMsg::Balance`.
+    Cw20,
+}
+
+impl AssetQueryKind {
+    /// Queries `owner`'s balance of `asset`, where `asset` is a native denom for
+    /// [`AssetQueryKind::Native`] or a contract address for the other two variants.
+    pub fn query_balance(&self, deps: Deps, asset: &str, owner: &Addr) -> StdResult<Uint128> {
+        match self {
+            AssetQueryKind::Native => {
+                let response: BankBalanceResponse =
+                    deps.querier.query(&QueryRequest::Bank(BankQuery::Balance {
+                        address: owner.to_string(),
+                        denom: asset.to_string(),
+                    }))?;
+                Ok(response.amount.amount)
+            }
+            AssetQueryKind::Cw20 => {
+                let response: Cw20BalanceResponse =
+                    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                        contract_addr: asset.to_string(),
+                        msg: to_binary(&Cw20QueryMsg::Balance {
+                            address: owner.to_string(),
+                        })?,
+                    }))?;
+                Ok(response.balance)
+            }
+            AssetQueryKind::SmartToken => {
+                let response: Cw20BalanceResponse =
+                    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                        contract_addr: asset.to_string(),
+                        msg: to_binary(&SmartTokenQueryMsg::Balance {
+                            address: owner.to_string(),
+                        })?,
+                    }))?;
+                Ok(response.balance)
+            }
+        }
+    }
+}
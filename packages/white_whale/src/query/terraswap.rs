@@ -1,9 +1,27 @@
 use cosmwasm_std::{
-    to_binary, Addr, Coin, Decimal, Deps, QueryRequest, StdResult, Uint128, WasmQuery,
+    to_binary, Addr, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, QueryRequest, StdError,
+    StdResult, Uint128, WasmMsg, WasmQuery,
+};
+use cw_storage_plus::Map;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use terraswap::pair::ExecuteMsg as PairExecuteMsg;
+
+use crate::astroport_helper::{
+    Asset, AssetInfo, PoolResponse, QueryMsg, ReverseSimulationResponse, SimulationResponse,
 };
-use crate::astroport_helper::{Asset, AssetInfo, PoolResponse, QueryMsg, SimulationResponse};
 
 pub fn simulate_swap(deps: Deps, pool_address: Addr, offer_coin: Coin) -> StdResult<Uint128> {
+    Ok(simulate_swap_full(deps, pool_address, offer_coin)?.return_amount)
+}
+
+// Like `simulate_swap` but returns the full response, including the spread and
+// commission amounts that callers need to reason about slippage.
+pub fn simulate_swap_full(
+    deps: Deps,
+    pool_address: Addr,
+    offer_coin: Coin,
+) -> StdResult<SimulationResponse> {
     let response: SimulationResponse =
         deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
             contract_addr: pool_address.to_string(),
@@ -17,9 +35,120 @@ pub fn simulate_swap(deps: Deps, pool_address: Addr, offer_coin: Coin) -> StdRes
             })?,
         }))?;
 
+    Ok(response)
+}
+
+// Simulates a swap and errors if the resulting slippage exceeds `max_spread`.
+// Slippage is measured as `spread_amount / (return_amount + spread_amount)`.
+pub fn simulate_swap_with_max_spread(
+    deps: Deps,
+    pool_address: Addr,
+    offer_coin: Coin,
+    max_spread: Decimal,
+) -> StdResult<Uint128> {
+    let response = simulate_swap_full(deps, pool_address, offer_coin)?;
+
+    let total = response.return_amount + response.spread_amount;
+    let spread = Decimal::from_ratio(response.spread_amount, total);
+    if spread > max_spread {
+        return Err(StdError::generic_err(format!(
+            "Max spread exceeded: spread {} > max {}",
+            spread, max_spread
+        )));
+    }
+
     Ok(response.return_amount)
 }
 
+// Returns the offer amount required to receive `ask_coin` from the pool, using the
+// pool's `ReverseSimulation` query.
+pub fn reverse_simulate_swap(deps: Deps, pool_address: Addr, ask_coin: Coin) -> StdResult<Uint128> {
+    let response: ReverseSimulationResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: pool_address.to_string(),
+            msg: to_binary(&QueryMsg::ReverseSimulation {
+                ask_asset: Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: ask_coin.denom,
+                    },
+                    amount: ask_coin.amount,
+                },
+            })?,
+        }))?;
+
+    Ok(response.offer_amount)
+}
+
+// Threads the output of `simulate_swap` through a chain of pools, returning the
+// final expected amount. Each hop is a (pool address, offer asset) pair; the offer
+// asset must match the denom currently held after the previous hop.
+pub fn simulate_route(
+    deps: Deps,
+    hops: Vec<(Addr, AssetInfo)>,
+    offer_coin: Coin,
+) -> StdResult<Uint128> {
+    let mut amount = offer_coin.amount;
+    for (pool_address, offer_asset_info) in hops {
+        // Each hop offers its own named asset, in the amount the previous hop returned
+        let denom = match offer_asset_info {
+            AssetInfo::NativeToken { denom } => denom,
+            AssetInfo::Token { .. } => {
+                return Err(StdError::generic_err(
+                    "simulate_route currently only supports native token legs",
+                ))
+            }
+        };
+        amount = simulate_swap(deps, pool_address, Coin { denom, amount })?;
+    }
+    Ok(amount)
+}
+
+// Builds the ordered `PairExecuteMsg::Swap` messages for a multi-hop route. Each
+// hop carries its own `belief_price`/`max_spread` so slippage can be bounded per leg.
+pub fn build_route_swap_msgs(
+    deps: Deps,
+    hops: Vec<(Addr, AssetInfo, Option<Decimal>, Option<Decimal>)>,
+    offer_coin: Coin,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut msgs = vec![];
+    let mut current = offer_coin;
+
+    for (pool_address, offer_asset_info, belief_price, max_spread) in hops {
+        // Like `simulate_route`, each hop offers the asset named alongside its pool
+        current.denom = match offer_asset_info {
+            AssetInfo::NativeToken { denom } => denom,
+            AssetInfo::Token { .. } => {
+                return Err(StdError::generic_err(
+                    "build_route_swap_msgs currently only supports native token legs",
+                ))
+            }
+        };
+        let offer_asset = Asset {
+            info: AssetInfo::NativeToken {
+                denom: current.denom.clone(),
+            },
+            amount: current.amount,
+        };
+
+        msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: pool_address.to_string(),
+            funds: vec![current.clone()],
+            msg: to_binary(&PairExecuteMsg::Swap {
+                offer_asset,
+                belief_price,
+                max_spread,
+                to: None,
+            })?,
+        }));
+
+        // Simulate this leg so the next hop's message is built with the expected
+        // amount of the asset it will actually receive.
+        current.amount = simulate_swap(deps, pool_address, current.clone())?;
+    }
+
+    Ok(msgs)
+}
+
 // perform a query for Pool information using the provided pool_address
 // return any response.
 // PoolResponse comes from terraswap and contains info on each of the assets as well as total share
@@ -41,3 +170,119 @@ pub fn pool_ratio(deps: Deps, pool_address: Addr) -> StdResult<Decimal> {
     let ratio = Decimal::from_ratio(response.assets[0].amount, response.assets[1].amount);
     Ok(ratio)
 }
+
+//----------------------------------------------------------------------------------------
+//  TWAP ORACLE
+//----------------------------------------------------------------------------------------
+
+/// A single TWAP accumulator snapshot for a pool. `cumulative_price` is the running sum
+/// of `spot_ratio * seconds_elapsed` since the first observation, in the style of a
+/// Uniswap-v2-like cumulative price oracle.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceObservation {
+    pub timestamp: u64,
+    pub cumulative_price: Decimal,
+}
+
+/// History of price observations per pool, ordered by `timestamp` ascending.
+pub const PRICE_OBSERVATIONS: Map<&Addr, Vec<PriceObservation>> = Map::new("price_observations");
+
+// Resolves the spot ratio `base/quote` for a pool, matching on `AssetInfo` instead of
+// assuming a hard-coded asset ordering.
+fn spot_ratio(
+    deps: Deps,
+    pool_address: &Addr,
+    base: &AssetInfo,
+    quote: &AssetInfo,
+) -> StdResult<Decimal> {
+    let pool = query_pool(deps, pool_address.clone())?;
+
+    let base_amount = pool
+        .assets
+        .iter()
+        .find(|asset| &asset.info == base)
+        .ok_or_else(|| StdError::generic_err("base asset not found in pool"))?
+        .amount;
+    let quote_amount = pool
+        .assets
+        .iter()
+        .find(|asset| &asset.info == quote)
+        .ok_or_else(|| StdError::generic_err("quote asset not found in pool"))?
+        .amount;
+
+    Ok(Decimal::from_ratio(base_amount, quote_amount))
+}
+
+// Records a new TWAP observation for `pool`, accumulating `spot_ratio * seconds_elapsed`
+// since the last stored observation. Observations must be strictly monotonic in time.
+pub fn record_price(
+    deps: DepsMut,
+    env: &Env,
+    pool_address: Addr,
+    base: AssetInfo,
+    quote: AssetInfo,
+) -> StdResult<PriceObservation> {
+    let now = env.block.time.seconds();
+    let spot = spot_ratio(deps.as_ref(), &pool_address, &base, &quote)?;
+
+    let mut history = PRICE_OBSERVATIONS
+        .may_load(deps.storage, &pool_address)?
+        .unwrap_or_default();
+
+    let cumulative_price = match history.last() {
+        Some(last) => {
+            if now <= last.timestamp {
+                return Err(StdError::generic_err(
+                    "price observations must be monotonic in time",
+                ));
+            }
+            let elapsed = now - last.timestamp;
+            last.cumulative_price + spot * Decimal::from_ratio(elapsed, 1u64)
+        }
+        None => Decimal::zero(),
+    };
+
+    let observation = PriceObservation {
+        timestamp: now,
+        cumulative_price,
+    };
+    history.push(observation.clone());
+    PRICE_OBSERVATIONS.save(deps.storage, &pool_address, &history)?;
+
+    Ok(observation)
+}
+
+// Returns the time-weighted average price over the last `window_secs`, using the
+// nearest observation that is at least `window_secs` old as the reference point.
+pub fn query_twap(
+    deps: Deps,
+    pool_address: Addr,
+    window_secs: u64,
+    now: u64,
+) -> StdResult<Decimal> {
+    let history = PRICE_OBSERVATIONS
+        .may_load(deps.storage, &pool_address)?
+        .unwrap_or_default();
+
+    let current = history
+        .last()
+        .ok_or_else(|| StdError::generic_err("no price observations recorded for this pool"))?;
+
+    let cutoff = now.saturating_sub(window_secs);
+    let reference = history
+        .iter()
+        .rev()
+        .find(|observation| observation.timestamp <= cutoff)
+        .ok_or_else(|| {
+            StdError::generic_err("not enough price history for the requested window")
+        })?;
+
+    let elapsed = current.timestamp - reference.timestamp;
+    if elapsed == 0 {
+        return Err(StdError::generic_err(
+            "insufficient elapsed time between observations",
+        ));
+    }
+
+    Ok((current.cumulative_price - reference.cumulative_price) * Decimal::from_ratio(1u64, elapsed))
+}
@@ -1,8 +1,21 @@
-use cosmwasm_std::{DepsMut, MessageInfo, Response, StdResult};
+use cosmwasm_std::{
+    Addr, Binary, Deps, DepsMut, MessageInfo, Order, Response, StdError, StdResult, Uint128,
+};
+use cw2::{get_contract_version, set_contract_version};
+use semver::Version;
 
-use crate::treasury::dapp_base::common::BaseDAppResult;
+use crate::query::balance::AssetQueryKind;
+use crate::query::terraswap::query_pool;
+use crate::treasury::dapp_base::common::{
+    AdapterEntry, AdapterKind, BaseDAppResult, ContractStatus, EmergencyAction,
+};
+use crate::treasury::dapp_base::error::BaseDAppError;
 use crate::treasury::dapp_base::msg::BaseExecuteMsg;
-use crate::treasury::dapp_base::state::{ADDRESS_BOOK, ADMIN, STATE};
+use crate::treasury::dapp_base::permit::{Permit, PermitOperation};
+use crate::treasury::dapp_base::state::{
+    ADDRESS_BOOK, ADMIN, CONTRACT_STATUS, DELEGATE_PUBKEY, EMERGENCY_OWNER, FROZEN,
+    REVOKED_PERMITS, STATE,
+};
 
 /// Handles the common base execute messages
 pub fn handle_base_message(deps: DepsMut, info: MessageInfo, message: BaseExecuteMsg) -> BaseDAppResult {
@@ -13,33 +26,131 @@ pub fn handle_base_message(deps: DepsMut, info: MessageInfo, message: BaseExecut
         } => update_config(deps, info, treasury_address, trader),
         BaseExecuteMsg::SetAdmin { admin } => set_admin(deps, info, admin),
         BaseExecuteMsg::UpdateAddressBook { to_add, to_remove } =>
-            update_address_book(deps, info, to_add, to_remove)
+            update_address_book(deps, info, to_add, to_remove),
+        BaseExecuteMsg::SetContractStatus { status } => set_contract_status(deps, info, status),
+        BaseExecuteMsg::EmergencyUpdate { action } => handle_emergency_update(deps, info, action),
+        BaseExecuteMsg::RevokePermit { id } => revoke_permit(deps, info, id),
+        BaseExecuteMsg::SetDelegatePubkey { pubkey } => set_delegate_pubkey(deps, info, pubkey),
     }
 }
 
+//----------------------------------------------------------------------------------------
+//  KILLSWITCH
+//----------------------------------------------------------------------------------------
+
+/// Sets the dApp's operational status. Gated by `ADMIN` so only a privileged role can
+/// halt or resume trading.
+pub fn set_contract_status(
+    deps: DepsMut,
+    msg_info: MessageInfo,
+    status: ContractStatus,
+) -> BaseDAppResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+    CONTRACT_STATUS.save(deps.storage, &status)?;
+    Ok(Response::new()
+        .add_attribute("action", "set_contract_status")
+        .add_attribute("status", format!("{:?}", status)))
+}
+
+/// Rejects the call unless the dApp is `Operational`. Every trading/liquidity handler
+/// (`SwapAsset`, `ProvideLiquidity`, `DetailedProvideLiquidity`, `WithdrawLiquidity`, ...)
+/// should call this first; base config/admin messages go through `handle_base_message`
+/// directly and are never gated, so a paused contract can still be recovered.
+pub fn assert_operational(deps: Deps) -> Result<(), BaseDAppError> {
+    let status = CONTRACT_STATUS.may_load(deps.storage)?.unwrap_or_default();
+    if status != ContractStatus::Operational {
+        return Err(BaseDAppError::ContractPaused {});
+    }
+    Ok(())
+}
+
+//----------------------------------------------------------------------------------------
+//  EMERGENCY FREEZES
+//----------------------------------------------------------------------------------------
+
+/// Applies an [`EmergencyAction`], surgically freezing the one swap pool, liquidity pool,
+/// or asset it names. Gated by `EMERGENCY_OWNER` or `ADMIN`, since the whole point of this
+/// role is to let someone other than the full admin ground a single position fast.
+pub fn handle_emergency_update(
+    deps: DepsMut,
+    msg_info: MessageInfo,
+    action: EmergencyAction,
+) -> BaseDAppResult {
+    assert_emergency_owner_or_admin(deps.as_ref(), &msg_info.sender)?;
+    FROZEN.save(deps.storage, action.frozen_key().as_str(), &true)?;
+    Ok(Response::new()
+        .add_attribute("action", "emergency_update")
+        .add_attribute("frozen", action.frozen_key()))
+}
+
+fn assert_emergency_owner_or_admin(deps: Deps, sender: &Addr) -> Result<(), BaseDAppError> {
+    if ADMIN.assert_admin(deps, sender).is_ok() {
+        return Ok(());
+    }
+    if EMERGENCY_OWNER.assert_admin(deps, sender).is_ok() {
+        return Ok(());
+    }
+    Err(BaseDAppError::NotEmergencyOwner {})
+}
+
+/// Rejects the call if `pool_id` has been frozen for swaps via `EmergencyAction::DisableSwap`.
+pub fn assert_swap_not_frozen(deps: Deps, pool_id: &str) -> Result<(), BaseDAppError> {
+    assert_not_frozen(deps, &EmergencyAction::DisableSwap(pool_id.to_string()).frozen_key())
+}
+
+/// Rejects the call if `pool_id` has been frozen for liquidity provision via
+/// `EmergencyAction::DisableProvide`.
+pub fn assert_provide_not_frozen(deps: Deps, pool_id: &str) -> Result<(), BaseDAppError> {
+    assert_not_frozen(deps, &EmergencyAction::DisableProvide(pool_id.to_string()).frozen_key())
+}
+
+/// Rejects the call if `asset_id` has been blocklisted via `EmergencyAction::BlocklistAsset`.
+pub fn assert_asset_not_frozen(deps: Deps, asset_id: &str) -> Result<(), BaseDAppError> {
+    assert_not_frozen(deps, &EmergencyAction::BlocklistAsset(asset_id.to_string()).frozen_key())
+}
+
+fn assert_not_frozen(deps: Deps, frozen_key: &str) -> Result<(), BaseDAppError> {
+    if FROZEN.may_load(deps.storage, frozen_key)?.unwrap_or(false) {
+        return Err(BaseDAppError::AssetFrozen {});
+    }
+    Ok(())
+}
+
 //----------------------------------------------------------------------------------------
 //  GOVERNANCE CONTROLLED SETTERS
 //----------------------------------------------------------------------------------------
 
-/// Adds, updates or removes provided addresses. 
+/// Adds, updates or removes provided addresses. Each added entry declares the kind of
+/// protocol it adapts (pair, oracle, money-market, generator/staking) and the kind of
+/// balance query it answers to (native/smart-token/CW20), so handlers can resolve a
+/// strongly-typed adapter via `resolve_pair`/`resolve_money_market` and query its balance
+/// through [`AssetQueryKind`] instead of string-munging conventions like `PAIR_POSTFIX` or
+/// hardcoding a CW20 query at every call site.
 pub fn update_address_book(
     deps: DepsMut,
     msg_info: MessageInfo,
-    to_add: Vec<(String, String)>,
+    to_add: Vec<(String, String, AdapterKind, AssetQueryKind)>,
     to_remove: Vec<String>,
 ) -> BaseDAppResult {
     // Only Admin can call this method
     ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
 
-    for (name, new_address) in to_add.into_iter() {
-        // Update function for new or existing keys
-        let insert = |vault_asset: Option<String>| -> StdResult<String> {
-            match vault_asset {
-                Some(_) => Ok(new_address),
-                None => Ok(new_address),
-            }
-        };
-        ADDRESS_BOOK.update(deps.storage, name.as_str(), insert)?;
+    for (name, new_address, kind, asset_kind) in to_add.into_iter() {
+        let validated = deps.api.addr_validate(&new_address)?;
+
+        // A pair entry must actually resolve to a pair contract, so a typo'd or
+        // wrong-kind address fails fast at registration time instead of at the first
+        // swap that tries to use it.
+        if kind == AdapterKind::Pair {
+            query_pool(deps.as_ref(), validated.clone())
+                .map_err(|_| BaseDAppError::NotPairContract {})?;
+        }
+
+        ADDRESS_BOOK.save(
+            deps.storage,
+            name.as_str(),
+            &AdapterEntry { kind, address: validated, asset_kind },
+        )?;
     }
 
     for name in to_remove {
@@ -49,6 +160,46 @@ pub fn update_address_book(
     Ok(Response::new().add_attribute("action", "updated address book"))
 }
 
+/// Lists every registered adapter together with its kind and resolved address.
+pub fn query_adapters(deps: Deps) -> StdResult<Vec<(String, AdapterEntry)>> {
+    ADDRESS_BOOK
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect()
+}
+
+/// Resolves `name`'s registered balance-query kind and queries `owner`'s balance of it.
+/// The `WithdrawLiquidity` handler does not call this; its LP-token self-balance lookup
+/// does not go through the entry's `AssetQueryKind`.
+pub fn query_adapter_balance(deps: Deps, name: &str, owner: &Addr) -> StdResult<Uint128> {
+    let entry = ADDRESS_BOOK.load(deps.storage, name)?;
+    entry.asset_kind.query_balance(deps, entry.address.as_str(), owner)
+}
+
+/// Resolves `name` to a strongly-typed adapter handle, enforcing it was registered as
+/// `expected_kind`. Used instead of trusting the address-book name alone to imply the
+/// message shape the resolved contract speaks.
+fn resolve_adapter(deps: Deps, name: &str, expected_kind: AdapterKind) -> StdResult<Addr> {
+    let entry = ADDRESS_BOOK.load(deps.storage, name)?;
+    if entry.kind != expected_kind {
+        return Err(StdError::generic_err(format!(
+            "adapter \"{}\" is registered as {:?}, not {:?}",
+            name, entry.kind, expected_kind
+        )));
+    }
+    Ok(entry.address)
+}
+
+/// Resolves a registered pair adapter by name (e.g. `"bluna_luna"`).
+pub fn resolve_pair(deps: Deps, name: &str) -> StdResult<Addr> {
+    resolve_adapter(deps, name, AdapterKind::Pair)
+}
+
+/// Resolves a registered money-market adapter by name (e.g. `ANCHOR_MONEY_MARKET_ID`),
+/// for use by the vault's passive strategy.
+pub fn resolve_money_market(deps: Deps, name: &str) -> StdResult<Addr> {
+    resolve_adapter(deps, name, AdapterKind::MoneyMarket)
+}
+
 /// Updates trader or treasury address
 pub fn update_config(
     deps: DepsMut,
@@ -82,4 +233,95 @@ pub fn set_admin(deps: DepsMut, info: MessageInfo, admin: String) -> BaseDAppRes
     Ok(Response::default()
         .add_attribute("previous admin", previous_admin)
         .add_attribute("admin", admin))
+}
+
+//----------------------------------------------------------------------------------------
+//  PERMIT AUTHORIZATION
+//----------------------------------------------------------------------------------------
+
+/// Authorizes a trading/liquidity call. Accepts either the on-chain trader contract (the
+/// existing `info.sender == trader` check) or a valid, unrevoked permit scoped to
+/// `operation` and this contract and signed by the registered `DELEGATE_PUBKEY` -- so a
+/// delegated key can trigger trades without holding the trader contract's own
+/// credentials, but only a key the admin actually registered, not any self-signed keypair
+/// an attacker generates on the spot.
+pub fn assert_trader_or_permit(
+    deps: Deps,
+    contract_address: &Addr,
+    sender: &Addr,
+    permit: Option<&Permit>,
+    operation: PermitOperation,
+) -> Result<(), BaseDAppError> {
+    let state = STATE.load(deps.storage)?;
+    if sender == &state.trader {
+        return Ok(());
+    }
+
+    let permit = permit.ok_or(BaseDAppError::Unauthorized {})?;
+    if REVOKED_PERMITS.may_load(deps.storage, permit.id.as_str())?.unwrap_or(false) {
+        return Err(BaseDAppError::Unauthorized {});
+    }
+    let trusted_pubkey = DELEGATE_PUBKEY
+        .may_load(deps.storage)?
+        .ok_or(BaseDAppError::Unauthorized {})?;
+    permit.verify(deps.api, contract_address, &operation, &trusted_pubkey)
+}
+
+/// Revokes a permit by id so it can no longer authorize calls, even if the signature
+/// itself is still valid. Gated by `ADMIN`, same as the other governance setters.
+pub fn revoke_permit(deps: DepsMut, msg_info: MessageInfo, id: String) -> BaseDAppResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+    REVOKED_PERMITS.save(deps.storage, id.as_str(), &true)?;
+    Ok(Response::new()
+        .add_attribute("action", "revoke_permit")
+        .add_attribute("id", id))
+}
+
+/// Registers the pubkey a permit must be signed by to authorize trades -- the trust
+/// anchor `assert_trader_or_permit` checks every permit's pubkey against. Gated by
+/// `ADMIN`, same as the other governance setters.
+pub fn set_delegate_pubkey(deps: DepsMut, msg_info: MessageInfo, pubkey: Binary) -> BaseDAppResult {
+    ADMIN.assert_admin(deps.as_ref(), &msg_info.sender)?;
+    DELEGATE_PUBKEY.save(deps.storage, &pubkey)?;
+    Ok(Response::new().add_attribute("action", "set_delegate_pubkey"))
+}
+
+//----------------------------------------------------------------------------------------
+//  MIGRATION
+//----------------------------------------------------------------------------------------
+
+/// Shared `migrate` implementation for every dApp embedding this base. Each dApp's own
+/// `migrate` entry point should call this with its own `cw2` contract name/version and an
+/// ordered list of `(from_version, transform)` steps to run for in-place upgrades.
+pub fn handle_base_migrate(
+    mut deps: DepsMut,
+    contract_name: &str,
+    contract_version: &str,
+    migrations: Vec<(&str, fn(DepsMut) -> StdResult<()>)>,
+) -> BaseDAppResult {
+    let stored = get_contract_version(deps.storage)?;
+
+    if stored.contract != contract_name {
+        return Err(BaseDAppError::InvalidMigration {});
+    }
+    // Compare numerically, not as strings -- "9.9.9" >= "10.0.0" lexicographically, which
+    // would reject a legitimate upgrade into a double-digit version as a downgrade.
+    let stored_version = Version::parse(&stored.version).map_err(|_| BaseDAppError::InvalidMigration {})?;
+    let new_version = Version::parse(contract_version).map_err(|_| BaseDAppError::InvalidMigration {})?;
+    if stored_version >= new_version {
+        return Err(BaseDAppError::InvalidMigration {});
+    }
+
+    for (from_version, transform) in migrations {
+        if stored.version.as_str() == from_version {
+            transform(deps.branch())?;
+        }
+    }
+
+    set_contract_version(deps.storage, contract_name, contract_version)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("previous_version", stored.version)
+        .add_attribute("new_version", contract_version))
 }
\ No newline at end of file
@@ -1,7 +1,76 @@
-use cosmwasm_std::Response;
+use cosmwasm_std::{Addr, Response};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::query::balance::AssetQueryKind;
 use crate::treasury::dapp_base::error::BaseDAppError;
 
-/// Postfix for LP pair addresses. 
+/// Postfix for LP pair addresses.
 pub const PAIR_POSTFIX: &str = "_pair";
-pub const ANCHOR_MONEY_MARKET_ID: &str = "anchor_money_market"
+pub const ANCHOR_MONEY_MARKET_ID: &str = "anchor_money_market";
 pub type BaseDAppResult = Result<Response, BaseDAppError>;
+
+/// The kind of external protocol a registered address-book entry resolves to. Dapp
+/// handlers dispatch on this instead of string-munging conventions like `PAIR_POSTFIX`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum AdapterKind {
+    Pair,
+    Oracle,
+    MoneyMarket,
+    Generator,
+    /// A plain CW20 token or LP-token address with no protocol-specific message shape
+    /// of its own -- registered so handlers can look it up by name, not validated
+    /// on insert the way a `Pair` is.
+    Token,
+}
+
+/// A single typed address-book entry: an external contract plus the kind of protocol it
+/// implements, so call sites can resolve a strongly-typed adapter handle instead of
+/// guessing the message shape from the registered name alone.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AdapterEntry {
+    pub kind: AdapterKind,
+    pub address: Addr,
+    /// How to query this entry's balance for a given owner. Lets `WithdrawLiquidity` and
+    /// swap handlers resolve LP-token and offer-asset balances on chains that don't speak
+    /// CW20, instead of hardcoding a `Cw20QueryMsg::Balance` query at every call site.
+    pub asset_kind: AssetQueryKind,
+}
+
+/// Coarse operational state for a dApp. Anything other than `Operational` blocks every
+/// trading/liquidity message, so an incident can be halted without revoking admin
+/// access -- base config/admin messages are handled separately and always go through.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum ContractStatus {
+    Operational,
+    Paused,
+    Migrating,
+}
+
+impl Default for ContractStatus {
+    fn default() -> Self {
+        ContractStatus::Operational
+    }
+}
+
+/// A single emergency freeze, scoped to one swap pool, one liquidity pool, or one asset,
+/// so an incident response can ground the specific position involved instead of pausing
+/// the whole dApp via [`ContractStatus`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub enum EmergencyAction {
+    DisableSwap(String),
+    DisableProvide(String),
+    BlocklistAsset(String),
+}
+
+impl EmergencyAction {
+    /// The `FROZEN` map key this action reads/writes, namespaced by kind so the same id
+    /// (e.g. a pool also named as an asset) can't collide across the three freeze kinds.
+    pub fn frozen_key(&self) -> String {
+        match self {
+            EmergencyAction::DisableSwap(pool_id) => format!("swap:{}", pool_id),
+            EmergencyAction::DisableProvide(pool_id) => format!("provide:{}", pool_id),
+            EmergencyAction::BlocklistAsset(asset_id) => format!("asset:{}", asset_id),
+        }
+    }
+}
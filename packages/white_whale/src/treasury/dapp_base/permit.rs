@@ -0,0 +1,72 @@
+use cosmwasm_std::{to_binary, Addr, Api, Binary};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::treasury::dapp_base::error::BaseDAppError;
+
+/// The operations an off-chain-signed permit can authorize, so a delegated key can be
+/// scoped to exactly what it's meant to trigger instead of blanket trading rights.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub enum PermitOperation {
+    Swap,
+    ProvideLiquidity,
+}
+
+/// Parameters a permit attests to: the operations it authorizes and the contract it's
+/// scoped to, so a permit signed for one dApp can't be replayed against another.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct PermitParams {
+    pub allowed_operations: Vec<PermitOperation>,
+    pub contract_address: String,
+}
+
+/// An off-chain secp256k1-signed permit, adapted from the SNIP-20 viewing-permit pattern:
+/// a caller attaches one of these to a trading message instead of relying solely on
+/// `info.sender == trader`, letting a delegated key (e.g. a trading bot's hot key)
+/// authorize trades without holding the trader contract's own credentials.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct Permit {
+    /// Identifies this permit in the revocation list; chosen by whoever signs it.
+    pub id: String,
+    pub params: PermitParams,
+    pub pubkey: Binary,
+    pub signature: Binary,
+}
+
+impl Permit {
+    /// Checks that the permit is scoped to `contract_address` and `operation`, that its
+    /// pubkey is the one registered in `DELEGATE_PUBKEY` (anyone can generate a keypair
+    /// and self-sign a consistent `PermitParams`, so the signature alone proves nothing
+    /// without also trusting the key), and only then recovers the signature over
+    /// `params` with secp256k1. Revocation is checked separately by the caller against
+    /// the `REVOKED_PERMITS` set, since that needs storage access this function doesn't
+    /// have.
+    pub fn verify(
+        &self,
+        api: &dyn Api,
+        contract_address: &Addr,
+        operation: &PermitOperation,
+        trusted_pubkey: &Binary,
+    ) -> Result<(), BaseDAppError> {
+        if &self.pubkey != trusted_pubkey {
+            return Err(BaseDAppError::Unauthorized {});
+        }
+        if self.params.contract_address != contract_address.as_str() {
+            return Err(BaseDAppError::Unauthorized {});
+        }
+        if !self.params.allowed_operations.contains(operation) {
+            return Err(BaseDAppError::Unauthorized {});
+        }
+
+        let sign_bytes = to_binary(&self.params)?;
+        let hash = Sha256::digest(sign_bytes.as_slice());
+        let valid = api
+            .secp256k1_verify(&hash, &self.signature, &self.pubkey)
+            .map_err(|_| BaseDAppError::Unauthorized {})?;
+        if !valid {
+            return Err(BaseDAppError::Unauthorized {});
+        }
+        Ok(())
+    }
+}